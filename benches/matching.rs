@@ -0,0 +1,108 @@
+//! Measures how long matching a profile against 6 to 8 heads takes.
+//!
+//! Run with `cargo bench --bench matching`.
+
+use std::collections::VecDeque;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use shikane::matching::ProfileMatcher;
+use shikane::profile::Profile;
+use shikane::settings::SettingsToml;
+use shikane::wl_backend::{WlBaseHead, WlBaseMode, WlGenericId, WlHead, WlMode};
+
+const ITERATIONS: u32 = 5;
+
+/// Heads with a single mode, so every perfect matching is exactly one variant.
+fn heads(count: usize) -> VecDeque<WlHead> {
+    (0..count)
+        .map(|id| {
+            let base = WlBaseMode {
+                width: 1920,
+                height: 1080,
+                refresh: 60000,
+                preferred: true,
+            };
+            let modes = vec![WlMode::new(WlGenericId::new(id * 100), base)].into();
+            let base = WlBaseHead {
+                name: format!("DP-{id}"),
+                make: "Generic".to_string(),
+                ..Default::default()
+            };
+            WlHead::new(WlGenericId::new(id), base, modes, None)
+        })
+        .collect()
+}
+
+/// Builds a profile with one output per search, placed side by side.
+fn profile(searches: &[String]) -> Profile {
+    let outputs: Vec<String> = searches
+        .iter()
+        .enumerate()
+        .map(|(idx, s)| {
+            let x = idx * 1920;
+            format!(
+                "{{ search = {s:?}, enable = true, mode = \"1920x1080\", position = \"{x},0\" }}"
+            )
+        })
+        .collect();
+    let config = format!(
+        "[[profile]]\nname = \"bench\"\noutput = [{}]",
+        outputs.join(", ")
+    );
+    let mut settings: SettingsToml = toml::from_str(&config).unwrap();
+    settings.profiles.pop_front().unwrap()
+}
+
+fn scenarios(count: usize) -> Vec<(&'static str, Vec<String>)> {
+    let specific = (0..count).map(|id| format!("n=DP-{id}")).collect();
+    let broad = (0..count).map(|_| "/DP".to_string()).collect();
+    let half_broad = (0..count)
+        .map(|id| match id % 2 {
+            0 => format!("n=DP-{id}"),
+            _ => "/DP".to_string(),
+        })
+        .collect();
+    // two outputs want the same head, so there is no perfect matching
+    let conflicting = (0..count)
+        .map(|id| match id {
+            0 => "n=DP-0".to_string(),
+            1 => "vn%DP-0".to_string(),
+            _ => "/DP".to_string(),
+        })
+        .collect();
+    vec![
+        ("specific", specific),
+        ("half broad", half_broad),
+        ("broad", broad),
+        ("conflicting", conflicting),
+    ]
+}
+
+fn main() {
+    println!(
+        "{:>5}  {:<12} {:>10} {:>12}",
+        "heads", "searches", "variants", "time/iter"
+    );
+    for count in 6..=8 {
+        let heads = heads(count);
+        for (name, searches) in scenarios(count) {
+            let profile = profile(&searches);
+            let mut elapsed = Duration::ZERO;
+            let mut variants = 0;
+            for _ in 0..ITERATIONS {
+                let start = Instant::now();
+                let report = ProfileMatcher::create_report(profile.clone(), heads.clone());
+                elapsed += start.elapsed();
+                variants = black_box(report).map_or(0, |r| r.variant_count());
+            }
+            println!(
+                "{:>5}  {:<12} {:>10} {:>12?}",
+                count,
+                name,
+                variants,
+                elapsed / ITERATIONS
+            );
+        }
+    }
+}
@@ -1,18 +1,23 @@
 mod args;
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use clap::Parser;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use snafu::{prelude::*, Location};
 
-use crate::client::args::IncludeSearchFields;
+use crate::client::args::{CmdHeads, CmdSimulate, Command, IncludeSearchFields};
 use crate::daemon::profile_manager::ProfileManager;
 use crate::error;
-use crate::ipc::{IpcRequest, IpcResponse, IpcStream};
-use crate::matching::MatchReport;
+use crate::ipc::{IpcResponse, IpcStream};
+use crate::matching::{MatchReport, Pairing, ProfileExplanation};
 use crate::profile::{ConvertError, ConverterSettings, Profile};
+use crate::settings;
 use crate::wl_backend::WlHead;
 
 pub use self::args::ShikaneCtl;
@@ -28,19 +33,25 @@ pub fn client(args: Option<ShikaneCtl>) {
 }
 
 fn run(args: ShikaneCtl) -> Result<(), Box<dyn snafu::Error>> {
+    let Some(request) = args.cmd.clone().into_request() else {
+        return run_locally(args.cmd);
+    };
     let mut ipc = match args.socket {
         Some(ref socket) => IpcStream::connect_to(socket)?,
         None => IpcStream::connect()?,
     };
 
-    let request: IpcRequest = args.cmd.clone().into();
     ipc.send(&request)?;
     let response: IpcResponse = ipc.recv()?;
     trace!("{response:?}");
 
     match response {
-        IpcResponse::CurrentHeads(heads) => print_current_configuration(args, heads)?,
+        IpcResponse::CurrentHeads(heads) => match args.cmd {
+            Command::Heads(c) => save_heads(c, heads)?,
+            _ => print_current_configuration(args, heads)?,
+        },
         IpcResponse::Error(err) => error!("{err}"),
+        IpcResponse::Explanations(explanations) => print_explanations(explanations),
         IpcResponse::Generic(s) => println!("{s}"),
         IpcResponse::MatchReports(reports) => print_match_reports(reports),
         IpcResponse::Success => {}
@@ -49,6 +60,130 @@ fn run(args: ShikaneCtl) -> Result<(), Box<dyn snafu::Error>> {
     Ok(())
 }
 
+/// Runs the commands that do not need the daemon.
+fn run_locally(cmd: Command) -> Result<(), Box<dyn snafu::Error>> {
+    if let Command::Simulate(c) = cmd {
+        print!("{}", simulate(c)?);
+    }
+    Ok(())
+}
+
+/// Runs the matching of the daemon on displays read from a file and describes the variants.
+fn simulate(cmd: CmdSimulate) -> Result<String, Box<dyn snafu::Error>> {
+    let s = std::fs::read_to_string(&cmd.heads).context(ReadHeadsCtx { path: &cmd.heads })?;
+    let heads: VecDeque<WlHead> = match cmd.heads.extension() {
+        Some(ext) if ext == "json" => serde_json::from_str(&s).context(JsonHeadsCtx)?,
+        _ => ron::de::from_str(&s).context(RonHeadsCtx)?,
+    };
+    let (config, _) = settings::parse_existing_settings_toml(cmd.config)?;
+
+    let mut pm = ProfileManager::new(config.profiles);
+    pm.set_max_variants(config.max_variants.map(NonZeroUsize::get));
+    pm.generate_variants(heads);
+    pm.wait_for_reports();
+    let mut out = String::new();
+    let mut count = 0;
+    while let Some(v) = pm.next_variant() {
+        count += 1;
+        out.push_str(&format!(
+            "{count}. variant {} of profile {:?} (specificity: {}, deviation: {})\n",
+            v.idx_str(),
+            v.profile.name,
+            v.specificity(),
+            v.mode_deviation()
+        ));
+        for pairing in v.pairings.iter() {
+            out.push_str(&format!("\t{}\n", describe_pairing(pairing)));
+        }
+    }
+    if count == 0 {
+        out.push_str("no variants, try `shikanectl explain` with a running daemon for details\n");
+    }
+    Ok(out)
+}
+
+/// Prints the displays or writes them to a file.
+fn save_heads(cmd: CmdHeads, mut heads: VecDeque<WlHead>) -> Result<(), ClientError> {
+    if cmd.anonymize {
+        anonymize_heads(&mut heads);
+    }
+    let s = match cmd.json {
+        true => serde_json::to_string_pretty(&heads).context(JsonSerializeCtx)?,
+        false => ron::ser::to_string_pretty(&heads, Default::default()).context(RonSerializeCtx)?,
+    };
+    match cmd.save {
+        Some(path) => std::fs::write(&path, s + "\n").context(WriteHeadsCtx { path })?,
+        None => println!("{s}"),
+    }
+    Ok(())
+}
+
+/// Replaces the serial numbers and EDID hashes with placeholders.
+///
+/// A placeholder is assigned to each distinct original value, so displays sharing a serial number
+/// or an EDID keep sharing their placeholder while all others get different ones. Empty serial
+/// numbers stay empty.
+fn anonymize_heads(heads: &mut VecDeque<WlHead>) {
+    let mut serials: HashMap<String, String> = HashMap::new();
+    let mut edids: HashMap<u64, String> = HashMap::new();
+    for head in heads.iter_mut() {
+        let serial = match head.serial_number() {
+            "" => String::new(),
+            serial => placeholder(&mut serials, serial.to_string(), "SERIAL"),
+        };
+        let edid_seed = match head.edid() {
+            Some(edid) => placeholder(&mut edids, edid.hash(), "EDID"),
+            None => String::new(),
+        };
+        head.anonymize(&serial, &edid_seed);
+    }
+}
+
+/// Returns the placeholder of the value, numbered in the order the values show up.
+fn placeholder<K: Eq + Hash>(
+    placeholders: &mut HashMap<K, String>,
+    key: K,
+    prefix: &str,
+) -> String {
+    let idx = placeholders.len();
+    placeholders
+        .entry(key)
+        .or_insert_with(|| format!("{prefix}{idx}"))
+        .clone()
+}
+
+/// Describes what a pairing would configure on its head.
+fn describe_pairing(pairing: &Pairing) -> String {
+    let output = pairing.output();
+    let mut s = format!(
+        "{:?} <- {:?}:",
+        pairing.wl_head().name(),
+        output.search_pattern.to_string()
+    );
+    if !output.enable {
+        s.push_str(" disabled");
+        return s;
+    }
+    match (pairing.custom_mode(), pairing.wl_mode()) {
+        (Some(mode), _) => s.push_str(&format!(" mode {mode}")),
+        (None, Some(mode)) => s.push_str(&format!(" mode {}", mode.wl_base_mode())),
+        (None, None) => s.push_str(" enabled"),
+    }
+    if let Some(position) = output.position {
+        s.push_str(&format!(", position {position}"));
+    }
+    if let Some(scale) = output.scale {
+        s.push_str(&format!(", scale {scale}"));
+    }
+    if let Some(transform) = output.transform {
+        s.push_str(&format!(", transform {transform}"));
+    }
+    if let Some(adaptive_sync) = output.adaptive_sync {
+        s.push_str(&format!(", adaptive_sync {adaptive_sync}"));
+    }
+    s
+}
+
 fn print_current_configuration(
     args: ShikaneCtl,
     heads: VecDeque<WlHead>,
@@ -74,11 +209,16 @@ fn print_current_configuration(
     Ok(())
 }
 
+fn print_explanations(explanations: Vec<ProfileExplanation>) {
+    let explanations: Vec<String> = explanations.iter().map(|e| e.to_string()).collect();
+    println!("{}", explanations.join("\n\n"));
+}
+
 fn print_match_reports(reports: VecDeque<MatchReport>) {
-    let variants = ProfileManager::collect_variants_from_reports(&reports);
-    let mut prev_profile: Option<Profile> = None;
-    println!("total valid variants: {}", variants.len());
-    for v in variants {
+    let mut variants = ProfileManager::rank_variants(&reports);
+    let mut prev_profile: Option<Arc<Profile>> = None;
+    println!("total valid variants: {}", variants.total());
+    for v in variants.by_ref() {
         match prev_profile {
             Some(ref profile) if *profile == v.profile => {}
             _ => {
@@ -87,12 +227,15 @@ fn print_match_reports(reports: VecDeque<MatchReport>) {
             }
         }
         println!(
-            "\t(specificity, deviation): ({}, {})",
+            "\t{} (specificity, deviation): ({}, {})",
+            v.idx_str(),
             v.specificity(),
             v.mode_deviation()
         );
     }
 
+    println!("duplicate variants removed: {}", variants.duplicate_count());
+
     println!();
     println!("[report specific values]");
     for r in reports {
@@ -109,9 +252,151 @@ fn print_match_reports(reports: VecDeque<MatchReport>) {
 pub enum ClientError {
     #[snafu(display("[{location}] Missing data from arguments for handling IPC response"))]
     CommandResponseMismatch { location: Location },
+    #[snafu(display("[{location}] Cannot read displays from {path:?}"))]
+    ReadHeads {
+        source: std::io::Error,
+        path: PathBuf,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot write displays to {path:?}"))]
+    WriteHeads {
+        source: std::io::Error,
+        path: PathBuf,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot serialize displays to RON"))]
+    RonSerialize {
+        source: ron::error::Error,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot serialize displays to JSON"))]
+    JsonSerialize {
+        source: serde_json::Error,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot deserialize displays from JSON"))]
+    JsonHeads {
+        source: serde_json::Error,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot deserialize displays from RON"))]
+    RonHeads {
+        source: ron::error::SpannedError,
+        location: Location,
+    },
     #[snafu(display("[{location}] Cannot convert current output configuration to TOML"))]
     Convert {
         source: ConvertError,
         location: Location,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::path::PathBuf;
+
+    use crate::client::args::CmdSimulate;
+    use crate::wl_backend::test_heads::{head, head_with_base};
+    use crate::wl_backend::{Edid, WlBaseHead, WlHead};
+
+    use super::{anonymize_heads, simulate};
+
+    /// Temporary directory removed after the test.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("shikane-client-{}-{test}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn heads() -> VecDeque<WlHead> {
+        ["eDP-1", "DP-1"]
+            .into_iter()
+            .enumerate()
+            .map(|(id, name)| head(id, name, &[60000]))
+            .collect()
+    }
+
+    #[test]
+    fn simulate_variants() {
+        let dir = TempDir::new("simulate");
+        let (heads_path, config_path) = (dir.0.join("heads.ron"), dir.0.join("config.toml"));
+        let content = ron::ser::to_string(&heads()).unwrap();
+        std::fs::write(&heads_path, content).unwrap();
+        let cmd = CmdSimulate {
+            heads: heads_path,
+            config: Some(config_path.clone()),
+        };
+        // the config file is not created
+        assert!(simulate(cmd.clone()).is_err());
+        assert!(!config_path.exists());
+
+        std::fs::write(
+            &config_path,
+            r#"
+            [[profile]]
+            name = "desk"
+            output = [
+                { search = "n=eDP-1", enable = false },
+                { search = "n=DP-1", enable = true, mode = "1920x1080@60Hz", position = "0,0", scale = 1.5 },
+            ]
+            "#,
+        )
+        .unwrap();
+        let out = simulate(cmd).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("1. variant 0,0 of profile \"desk\""));
+        assert_eq!(
+            lines[1],
+            "\t\"DP-1\" <- \"n=DP-1\": mode 1920x1080@60000mHz, position 0,0, scale 1.5"
+        );
+        assert_eq!(lines[2], "\t\"eDP-1\" <- \"n=eDP-1\": disabled");
+    }
+
+    #[test]
+    fn anonymize_distinct_heads() {
+        let edid = |product: u8| {
+            let mut blob = [0; 128];
+            blob[..8].copy_from_slice(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+            blob[10] = product;
+            Edid::parse(&blob).unwrap()
+        };
+        let mut heads: VecDeque<WlHead> = [("", 1), ("ABC", 2), ("", 3), ("ABC", 2), ("", 1)]
+            .into_iter()
+            .enumerate()
+            .map(|(id, (serial, product))| {
+                let base = WlBaseHead {
+                    serial_number: serial.to_string(),
+                    edid: Some(edid(product)),
+                    ..Default::default()
+                };
+                head_with_base(id, base, &[])
+            })
+            .collect();
+        let original: Vec<u64> = heads.iter().map(|h| h.edid().unwrap().hash()).collect();
+        anonymize_heads(&mut heads);
+
+        let serials: Vec<_> = heads.iter().map(|h| h.serial_number()).collect();
+        assert_eq!(serials, ["", "SERIAL0", "", "SERIAL0", ""]);
+        let hashes: Vec<u64> = heads.iter().map(|h| h.edid().unwrap().hash()).collect();
+        assert!(hashes.iter().all(|hash| !original.contains(hash)));
+        // equal EDIDs keep being equal, different ones stay different
+        assert_eq!(hashes[0], hashes[4]);
+        assert_eq!(hashes[1], hashes[3]);
+        assert_ne!(hashes[0], hashes[1]);
+        assert_ne!(hashes[0], hashes[2]);
+        assert_ne!(hashes[1], hashes[2]);
+    }
+}
@@ -25,6 +25,16 @@ pub enum Command {
     Switch(CmdSwitch),
     Reload(CmdReload),
     Export(CmdExport),
+    Explain(CmdExplain),
+    Simulate(CmdSimulate),
+    Heads(CmdHeads),
+    Confirm(CmdConfirm),
+    Revert(CmdRevert),
+    Unpin(CmdUnpin),
+    Next(CmdNext),
+    Prev(CmdPrev),
+    Pause(CmdPause),
+    Resume(CmdResume),
 }
 
 /// Subcommand for debugging shikane and its configuration.
@@ -40,8 +50,78 @@ pub enum CmdDebug {
 /// Use the given profile temporarily
 #[derive(Clone, Debug, Args)]
 pub struct CmdSwitch {
-    /// Name of the profile
-    name: String,
+    /// Name of the profile or a search for it, e.g. %desk
+    #[arg(required_unless_present = "variant")]
+    name: Option<String>,
+    /// Apply exactly this variant, as listed by `shikanectl debug list-reports`
+    #[arg(long, value_name = "PROFILE,VARIANT", value_parser = parse_variant_index, conflicts_with_all = ["name", "pin"])]
+    variant: Option<(usize, usize)>,
+    /// Keep using the profile after the displays change, until `shikanectl unpin`
+    #[arg(long)]
+    pin: bool,
+    /// Save the pin and use the profile whenever the same displays are connected
+    #[arg(long, requires = "pin")]
+    persist: bool,
+}
+
+/// Keep the profile that has just been applied instead of reverting it
+#[derive(Clone, Debug, Args)]
+pub struct CmdConfirm {}
+
+/// Switch to the next profile fitting the connected displays
+#[derive(Clone, Debug, Args)]
+pub struct CmdNext {}
+
+/// Switch to the previous profile fitting the connected displays
+#[derive(Clone, Debug, Args)]
+pub struct CmdPrev {}
+
+/// Stop reacting to changes of the displays until `shikanectl resume`
+#[derive(Clone, Debug, Args)]
+pub struct CmdPause {}
+
+/// React to changes of the displays again, starting with the current displays
+#[derive(Clone, Debug, Args)]
+pub struct CmdResume {}
+
+/// Remove the pinned profile, also the saved one for the connected displays
+#[derive(Clone, Debug, Args)]
+pub struct CmdUnpin {}
+
+/// Restore the displays to their state before the last profile was applied
+#[derive(Clone, Debug, Args)]
+pub struct CmdRevert {}
+
+/// Explain why profiles do or do not match the current displays
+#[derive(Clone, Debug, Args)]
+pub struct CmdExplain {
+    /// Only explain the profile with this name
+    profile: Option<String>,
+}
+
+/// Print the current displays with all their modes, e.g. for `shikanectl simulate`
+#[derive(Clone, Debug, Args)]
+pub struct CmdHeads {
+    /// Write the displays to this file instead of printing them
+    #[arg(long)]
+    pub save: Option<PathBuf>,
+    /// Use JSON instead of RON
+    #[arg(long)]
+    pub json: bool,
+    /// Replace serial numbers and EDID hashes with placeholders
+    #[arg(long)]
+    pub anonymize: bool,
+}
+
+/// Match recorded displays against a config without a running daemon
+#[derive(Clone, Debug, Args)]
+pub struct CmdSimulate {
+    /// RON or JSON (.json) file containing a list of displays
+    #[arg(long)]
+    pub heads: PathBuf,
+    /// Use this file instead of the default config file
+    #[arg(long)]
+    pub config: Option<PathBuf>,
 }
 
 /// Reload the configuration file
@@ -80,6 +160,15 @@ pub struct IncludeSearchFields {
     /// Include the vendor in the searches
     #[arg(short, long, group = "include_search_fields")]
     vendor: bool,
+    /// Include the EDID product code in the searches
+    #[arg(short = 'p', long, group = "include_search_fields")]
+    edid_product: bool,
+    /// Include the EDID manufacture year in the searches
+    #[arg(short = 'y', long, group = "include_search_fields")]
+    edid_year: bool,
+    /// Include the EDID hash in the searches
+    #[arg(short = 'e', long, group = "include_search_fields")]
+    edid_hash: bool,
 }
 
 impl Default for IncludeSearchFields {
@@ -90,6 +179,9 @@ impl Default for IncludeSearchFields {
             model: true,
             serial: true,
             vendor: true,
+            edid_product: false,
+            edid_year: false,
+            edid_hash: false,
         }
     }
 }
@@ -112,18 +204,46 @@ impl From<IncludeSearchFields> for Vec<SearchField> {
         if value.vendor {
             sf.push(SearchField::Vendor);
         }
+        if value.edid_product {
+            sf.push(SearchField::EdidProduct);
+        }
+        if value.edid_year {
+            sf.push(SearchField::EdidYear);
+        }
+        if value.edid_hash {
+            sf.push(SearchField::EdidHash);
+        }
         sf
     }
 }
 
-impl From<Command> for IpcRequest {
-    fn from(cmd: Command) -> Self {
-        match cmd {
+impl Command {
+    /// Returns the request to send to the daemon, or nothing if the command runs locally.
+    pub(super) fn into_request(self) -> Option<IpcRequest> {
+        let request = match self {
             Command::Debug(c) => c.into(),
-            Command::Switch(c) => Self::SwitchProfile(c.name),
-            Command::Reload(c) => Self::ReloadConfig(c.file),
-            Command::Export(_) => Self::CurrentHeads,
-        }
+            Command::Switch(c) => match c.variant {
+                Some((profile, variant)) => IpcRequest::SwitchVariant { profile, variant },
+                None => IpcRequest::SwitchProfile {
+                    name: c.name.unwrap_or_default(),
+                    pin: c.pin,
+                    persist: c.persist,
+                },
+            },
+            Command::Reload(c) => IpcRequest::ReloadConfig(c.file),
+            Command::Export(_) => IpcRequest::CurrentHeads,
+            Command::Explain(c) => IpcRequest::Explain(c.profile),
+            Command::Heads(_) => IpcRequest::CurrentHeads,
+            Command::Simulate(_) => return None,
+            Command::Confirm(_) => IpcRequest::Confirm,
+            Command::Revert(_) => IpcRequest::Revert,
+            Command::Unpin(_) => IpcRequest::Unpin,
+            Command::Next(_) => IpcRequest::NextProfile,
+            Command::Prev(_) => IpcRequest::PreviousProfile,
+            Command::Pause(_) => IpcRequest::Pause,
+            Command::Resume(_) => IpcRequest::Resume,
+        };
+        Some(request)
     }
 }
 
@@ -135,3 +255,14 @@ impl From<CmdDebug> for IpcRequest {
         }
     }
 }
+
+fn parse_variant_index(arg: &str) -> Result<(usize, usize), String> {
+    let parse = |idx: &str| idx.trim().parse::<usize>().ok();
+    match arg.split_once(',') {
+        Some((profile, variant)) => parse(profile).zip(parse(variant)),
+        None => None,
+    }
+    .ok_or_else(|| {
+        format!("invalid variant {arg:?}, expected \"<PROFILE>,<VARIANT>\" e.g. \"0,1\"")
+    })
+}
@@ -1,5 +1,8 @@
 pub mod ipc;
+pub mod match_pool;
+pub mod pins;
 pub mod profile_manager;
+pub mod replay;
 pub mod state_machine;
 
 use std::collections::VecDeque;
@@ -8,19 +11,22 @@ use std::path::PathBuf;
 use std::time::Duration;
 
 use calloop::{timer::Timer, EventLoop, LoopHandle, LoopSignal, RegistrationToken};
-use clap::Parser;
+use calloop_wayland_source::WaylandSource;
+use clap::{Parser, Subcommand};
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use snafu::{prelude::*, Location};
 
+use crate::daemon::pins::PinStore;
 use crate::daemon::state_machine::DaemonStateMachine;
 use crate::error;
-use crate::ipc::SocketBindCtx;
+use crate::ipc::{IpcRequest, IpcStream, SocketBindCtx};
 use crate::settings::Settings;
-use crate::wl_backend::{WlBackend, WlBackendEvent};
+use crate::wl_backend::{MockBackend, MockHotplug, RecordingBackend, WlBackend, WlBackendEvent};
 use crate::wlroots::WlrootsBackend;
 
-type DSMWlroots = DaemonStateMachine<WlrootsBackend>;
+/// How long a hotplug step of the mock backend waits before checking again if the daemon is busy.
+const HOTPLUG_RETRY_DELAY: Duration = Duration::from_millis(1);
 
 #[derive(Debug, Parser)]
 #[command(version)]
@@ -49,6 +55,48 @@ pub struct ShikaneArgs {
     /// Usually you should not set this as it slows down shikane.
     #[arg(short = 'T', long, default_value_t = 0)]
     pub timeout: u64,
+
+    /// Check the config file for errors and exit
+    #[arg(long)]
+    pub check: bool,
+
+    /// Match and test profiles but never apply them
+    ///
+    /// Neither are commands executed. The variant that would have been
+    /// applied is logged and shown by "shikanectl debug current-state".
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Backend that configures the outputs
+    ///
+    /// Either "wlroots" or "mock=<SCRIPT>" to simulate heads and
+    /// responses from a script file without a Wayland compositor.
+    #[arg(long, value_name = "BACKEND", default_value = "wlroots", value_parser = parse_backend)]
+    pub backend: BackendArg,
+
+    /// Record backend events, head exports, backend requests and IPC requests to FILE
+    ///
+    /// The recording can be checked with "shikane replay FILE".
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<ShikaneCommand>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ShikaneCommand {
+    /// Replay a recording and check that the daemon makes the same requests
+    Replay {
+        /// Recording made with --record
+        file: PathBuf,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub enum BackendArg {
+    Wlroots,
+    Mock(PathBuf),
 }
 
 pub struct Shikane<'a, B: WlBackend> {
@@ -57,6 +105,8 @@ pub struct Shikane<'a, B: WlBackend> {
     el_handle: LoopHandle<'a, Shikane<'a, B>>,
     loop_signal: LoopSignal,
     timeout_token: RegistrationToken,
+    // requests that are answered after the reports of the worker pool have been received
+    deferred_requests: Vec<(IpcStream, IpcRequest)>,
 }
 
 pub fn daemon(args: Option<ShikaneArgs>) {
@@ -64,6 +114,26 @@ pub fn daemon(args: Option<ShikaneArgs>) {
         Some(args) => args,
         None => ShikaneArgs::parse(),
     };
+    if args.check {
+        match crate::settings::check_config(args.config) {
+            Ok(true) => std::process::exit(0),
+            Ok(false) => std::process::exit(1),
+            Err(err) => {
+                error!("{}", error::report(err.as_ref()));
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(ShikaneCommand::Replay { file }) = &args.command {
+        match replay::replay(file) {
+            Ok(true) => std::process::exit(0),
+            Ok(false) => std::process::exit(1),
+            Err(err) => {
+                error!("{}", error::report(err.as_ref()));
+                std::process::exit(1);
+            }
+        }
+    }
     if let Err(err) = run(args) {
         error!("{}", error::report(err.as_ref()))
     }
@@ -71,50 +141,86 @@ pub fn daemon(args: Option<ShikaneArgs>) {
 
 fn run(args: ShikaneArgs) -> Result<(), Box<dyn snafu::Error>> {
     let arg_socket_path = args.socket.clone();
+    let backend = args.backend.clone();
+    let record = args.record.clone();
     let settings = Settings::from_args(args);
+    // the displays of the mock backend do not use the pins of the real ones
+    let pin_store = match backend {
+        BackendArg::Wlroots => PinStore::load().unwrap_or_else(|err| {
+            warn!("{}", error::report(&err));
+            Default::default()
+        }),
+        BackendArg::Mock(_) => Default::default(),
+    };
 
-    let (wlroots_backend, wl_source) = WlrootsBackend::connect()?;
-    let mut event_loop: EventLoop<Shikane<WlrootsBackend>> =
-        EventLoop::try_new().context(ELCreateCtx)?;
-    let el_handle = event_loop.handle();
+    match (backend, record) {
+        (BackendArg::Wlroots, None) => {
+            let (wlroots_backend, wl_source) = WlrootsBackend::connect()?;
+            run_with_backend(
+                wlroots_backend,
+                settings,
+                pin_store,
+                arg_socket_path,
+                |_, el_handle| insert_wayland_source(el_handle, wl_source),
+            )?;
+        }
+        (BackendArg::Wlroots, Some(path)) => {
+            let (wlroots_backend, wl_source) = WlrootsBackend::connect()?;
+            let backend =
+                RecordingBackend::new(wlroots_backend, &path, &settings, pin_store.pins())?;
+            run_with_backend(
+                backend,
+                settings,
+                pin_store,
+                arg_socket_path,
+                |_, el_handle| insert_wayland_source(el_handle, wl_source),
+            )?;
+        }
+        (BackendArg::Mock(script), None) => {
+            let mock_backend = MockBackend::from_file(&script)?;
+            run_with_backend(
+                mock_backend,
+                settings,
+                pin_store,
+                arg_socket_path,
+                insert_mock_sources,
+            )?;
+        }
+        (BackendArg::Mock(script), Some(path)) => {
+            let mock_backend = MockBackend::from_file(&script)?;
+            let backend = RecordingBackend::new(mock_backend, &path, &settings, pin_store.pins())?;
+            run_with_backend(
+                backend,
+                settings,
+                pin_store,
+                arg_socket_path,
+                insert_mock_sources,
+            )?;
+        }
+    }
+    Ok(())
+}
 
-    // wayland backend
-    el_handle
-        .insert_source(wl_source, move |_, event_queue, shikane| {
-            let dispatch_result = event_queue.dispatch_pending(&mut shikane.dsm.backend);
-            let n = match dispatch_result {
-                Ok(n) => n,
-                Err(ref err) => {
-                    error!("{}", error::report(&err));
-                    return dispatch_result;
-                }
-            };
-            trace!("dispatched {n} wayland events");
+/// Runs the daemon until the state machine shuts down and returns the backend.
+///
+/// `insert_sources` registers the event sources which feed the backend events into the loop.
+fn run_with_backend<B, F>(
+    backend: B,
+    settings: Settings,
+    pin_store: PinStore,
+    arg_socket_path: Option<PathBuf>,
+    insert_sources: F,
+) -> Result<B, Box<dyn snafu::Error>>
+where
+    B: WlBackend,
+    F: for<'a> FnOnce(&mut B, &LoopHandle<'a, Shikane<'a, B>>) -> Result<(), Box<dyn snafu::Error>>,
+{
+    let mut backend = backend;
+    let mut event_loop: EventLoop<Shikane<B>> = EventLoop::try_new().context(ELCreateCtx)?;
+    let el_handle = event_loop.handle();
 
-            let mut eq = shikane.dsm.backend.drain_event_queue();
-            shikane.event_queue.append(&mut eq);
-            let mut timeout = Duration::from_millis(0);
-            // delay processing only on change
-            if shikane
-                .event_queue
-                .contains(&WlBackendEvent::AtomicChangeDone)
-            {
-                timeout = shikane.dsm.settings.timeout;
-                trace!("delay processing by {:?}", timeout);
-            }
-            trace!(
-                "inserting new {:?} timer, replacing old {:?}",
-                timeout,
-                shikane.timeout_token
-            );
-            shikane.el_handle.remove(shikane.timeout_token);
-            match insert_timer(&shikane.el_handle, timeout) {
-                Ok(token) => shikane.timeout_token = token,
-                Err(err) => error!("{}", error::report(&err)),
-            }
-            dispatch_result
-        })
-        .context(InsertCtx)?;
+    // backend
+    insert_sources(&mut backend, &el_handle)?;
 
     // IPC socket
     let socket_path = match arg_socket_path {
@@ -139,7 +245,21 @@ fn run(args: ShikaneArgs) -> Result<(), Box<dyn snafu::Error>> {
     // initial timeout
     let timeout_token = insert_timer(&el_handle, settings.timeout)?;
 
-    let dsm = DSMWlroots::new(wlroots_backend, settings);
+    let mut dsm = DaemonStateMachine::new(backend, settings);
+    dsm.pm.set_pin_store(pin_store);
+
+    // reports of the matching workers
+    if let Some(channel) = dsm.pm.take_report_channel() {
+        el_handle
+            .insert_source(channel, |event, _, shikane| {
+                if let calloop::channel::Event::Msg(result) = event {
+                    shikane.dsm.receive_reports(result);
+                    ipc::answer_deferred_requests(shikane);
+                    shikane.state_machine_advanced();
+                }
+            })
+            .context(InsertCtx)?;
+    }
     let loop_signal = event_loop.get_signal();
     let mut shikane = Shikane {
         dsm,
@@ -147,6 +267,7 @@ fn run(args: ShikaneArgs) -> Result<(), Box<dyn snafu::Error>> {
         el_handle,
         loop_signal,
         timeout_token,
+        deferred_requests: vec![],
     };
     event_loop
         .run(
@@ -161,9 +282,124 @@ fn run(args: ShikaneArgs) -> Result<(), Box<dyn snafu::Error>> {
             },
         )
         .context(RunCtx)?;
+    Ok(shikane.dsm.backend)
+}
+
+fn insert_wayland_source<B: WlBackend + AsMut<WlrootsBackend>>(
+    el_handle: &LoopHandle<Shikane<B>>,
+    wl_source: WaylandSource<WlrootsBackend>,
+) -> Result<(), Box<dyn snafu::Error>> {
+    el_handle
+        .insert_source(wl_source, move |_, event_queue, shikane| {
+            let dispatch_result = event_queue.dispatch_pending(shikane.dsm.backend.as_mut());
+            let n = match dispatch_result {
+                Ok(n) => n,
+                Err(ref err) => {
+                    error!("{}", error::report(&err));
+                    return dispatch_result;
+                }
+            };
+            trace!("dispatched {n} wayland events");
+
+            shikane.queue_backend_events();
+            dispatch_result
+        })
+        .context(InsertCtx)?;
     Ok(())
 }
 
+fn insert_mock_sources<B: WlBackend + AsMut<MockBackend>>(
+    backend: &mut B,
+    el_handle: &LoopHandle<Shikane<B>>,
+) -> Result<(), Box<dyn snafu::Error>> {
+    let mock_backend = backend.as_mut();
+    let ping_source = mock_backend.event_source()?;
+    el_handle
+        .insert_source(ping_source, |_, _, shikane| shikane.queue_backend_events())
+        .context(InsertCtx)?;
+
+    // the next hotplug step is scheduled by the previous one
+    if let Some(step) = mock_backend.next_hotplug_step() {
+        insert_hotplug_timer(el_handle, step)?;
+    }
+    Ok(())
+}
+
+/// Performs the hotplug step once the daemon has dealt with everything before it and its delay
+/// has passed, so the order of the steps and the requests of the daemon is deterministic.
+fn insert_hotplug_timer<B: WlBackend + AsMut<MockBackend>>(
+    el_handle: &LoopHandle<Shikane<B>>,
+    step: MockHotplug,
+) -> Result<RegistrationToken, EventLoopInsertError<Timer>> {
+    let timer = Timer::from_duration(Duration::from_millis(step.delay));
+    let token = el_handle
+        .insert_source(timer, move |_, _, shikane| {
+            let pending_events = shikane.dsm.backend.as_mut().has_queued_events();
+            if pending_events || !shikane.event_queue.is_empty() || shikane.dsm.is_busy() {
+                return calloop::timer::TimeoutAction::ToDuration(HOTPLUG_RETRY_DELAY);
+            }
+            let mock_backend = shikane.dsm.backend.as_mut();
+            mock_backend.hotplug(&step.event);
+            if let Some(next) = mock_backend.next_hotplug_step() {
+                if let Err(err) = insert_hotplug_timer(&shikane.el_handle, next) {
+                    error!("{}", error::report(&err));
+                }
+            }
+            calloop::timer::TimeoutAction::Drop
+        })
+        .context(InsertCtx)?;
+    Ok(token)
+}
+
+impl<'a, B: WlBackend> Shikane<'a, B> {
+    /// Moves the events of the backend into the event queue and (re)schedules its processing.
+    fn queue_backend_events(&mut self) {
+        let mut eq = self.dsm.backend.drain_event_queue();
+        self.event_queue.append(&mut eq);
+        let mut timeout = Duration::from_millis(0);
+        // delay processing only on change
+        if self.event_queue.contains(&WlBackendEvent::AtomicChangeDone) {
+            timeout = self.dsm.settings.timeout;
+            trace!("delay processing by {:?}", timeout);
+        }
+        trace!(
+            "inserting new {:?} timer, replacing old {:?}",
+            timeout,
+            self.timeout_token
+        );
+        self.el_handle.remove(self.timeout_token);
+        match insert_timer(&self.el_handle, timeout) {
+            Ok(token) => self.timeout_token = token,
+            Err(err) => error!("{}", error::report(&err)),
+        }
+    }
+
+    /// Stops the event loop if the state machine shut down, otherwise sets up the timer of a
+    /// confirmation it awaits.
+    fn state_machine_advanced(&mut self) {
+        if self.dsm.has_shutdown() {
+            trace!("stopping event loop");
+            self.loop_signal.stop();
+            return;
+        }
+        if let Some((id, timeout)) = self.dsm.take_confirm_timer() {
+            if let Err(err) = insert_confirm_timer(&self.el_handle, id, timeout) {
+                error!("{}", error::report(&err));
+            }
+        }
+    }
+}
+
+fn parse_backend(arg: &str) -> Result<BackendArg, String> {
+    match arg.split_once('=') {
+        None if arg == "wlroots" => Ok(BackendArg::Wlroots),
+        Some(("mock", script)) if !script.is_empty() => Ok(BackendArg::Mock(script.into())),
+        _ => Err(format!(
+            "unknown backend {arg:?}, expected \"wlroots\" or \"mock=<SCRIPT>\""
+        )),
+    }
+}
+
 fn clean_up_socket(socket_path: &PathBuf) {
     match std::fs::remove_file(socket_path) {
         Ok(_) => trace!("Deleted stale socket from previous run"),
@@ -183,17 +419,31 @@ fn insert_timer<B: WlBackend>(
         .insert_source(timer, move |_instant, _, shikane| {
             trace!("processing event queue");
             let eq = std::mem::take(&mut shikane.event_queue);
-            let sm_shutdown = shikane.dsm.process_event_queue(eq);
-            if sm_shutdown {
-                trace!("stopping event loop");
-                shikane.loop_signal.stop();
-            }
+            let _ = shikane.dsm.process_event_queue(eq);
+            shikane.state_machine_advanced();
             calloop::timer::TimeoutAction::Drop
         })
         .context(InsertCtx)?;
     Ok(timeout_token)
 }
 
+fn insert_confirm_timer<B: WlBackend>(
+    el_handle: &LoopHandle<Shikane<B>>,
+    id: u64,
+    duration: Duration,
+) -> Result<RegistrationToken, EventLoopInsertError<Timer>> {
+    let timer = Timer::from_duration(duration);
+    let token = el_handle
+        .insert_source(timer, move |_instant, _, shikane| {
+            trace!("checking confirmation {id}");
+            shikane.dsm.check_confirmation(id);
+            shikane.state_machine_advanced();
+            calloop::timer::TimeoutAction::Drop
+        })
+        .context(InsertCtx)?;
+    Ok(token)
+}
+
 #[derive(Debug, Snafu)]
 #[snafu(context(suffix(Ctx)))]
 #[snafu(visibility(pub(crate)))]
@@ -223,3 +473,481 @@ where
         location: Location,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use crate::ipc::{IpcRequest, IpcResponse};
+    use crate::settings::{Settings, SettingsToml};
+    use crate::wl_backend::head_set_key;
+    use crate::wl_backend::{
+        test_heads, MockBackend, MockHotplug, MockHotplugEvent, MockRequestKind, MockResponse,
+        MockRule, MockScript, RecordingBackend, WlBackend, WlHead,
+    };
+
+    use super::ipc::delegate_command;
+    use super::pins::{PersistedPin, PinStore};
+    use super::profile_manager::ProfileManager;
+    use super::state_machine::DSMState;
+    use super::state_machine::DaemonStateMachine;
+    use super::{insert_mock_sources, replay, run_with_backend};
+
+    const CONFIG: &str = r#"
+        [[profile]]
+        name = "desk"
+        output = [
+            { search = "n=eDP-1", enable = false },
+            { search = "n=DP-1", enable = true, mode = "1920x1080", position = "0,0" },
+        ]
+        [[profile]]
+        name = "laptop"
+        output = [{ search = "n=eDP-1", enable = true, mode = "1920x1080" }]
+    "#;
+
+    fn head(id: usize, name: &str) -> WlHead {
+        test_heads::head(id, name, &[60000, 50000])
+    }
+
+    fn settings(oneshot: bool) -> Settings {
+        let mut config: SettingsToml = toml::from_str(CONFIG).unwrap();
+        config
+            .profiles
+            .iter_mut()
+            .enumerate()
+            .for_each(|(idx, p)| p.index = idx);
+        Settings {
+            profiles: config.profiles,
+            skip_tests: false,
+            oneshot,
+            timeout: Duration::ZERO,
+            config_path: PathBuf::new(),
+            max_variants: None,
+            max_attempts: None,
+            dry_run: false,
+            confirm_timeout: None,
+        }
+    }
+
+    /// Adds a profile that extends the desktop to both displays.
+    fn extended_settings(oneshot: bool) -> Settings {
+        let mut settings = settings(oneshot);
+        let extended: SettingsToml = toml::from_str(
+            r#"
+            [[profile]]
+            name = "extended"
+            output = [
+                { search = "n=eDP-1", enable = true, mode = "1920x1080", position = "0,0" },
+                { search = "n=DP-1", enable = true, mode = "1920x1080", position = "1920,0" },
+            ]
+            "#,
+        )
+        .unwrap();
+        for mut profile in extended.profiles {
+            profile.index = settings.profiles.len();
+            settings.profiles.push_back(profile);
+        }
+        settings
+    }
+
+    /// Processes everything like the event loop until the daemon waits for nothing.
+    fn settle<B: WlBackend>(dsm: &mut DaemonStateMachine<B>) {
+        loop {
+            dsm.wait_for_reports();
+            let eq = dsm.backend.drain_event_queue();
+            if eq.is_empty() {
+                break;
+            }
+            assert!(!dsm.process_event_queue(eq));
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("shikane-test-{}-{name}", std::process::id()))
+    }
+
+    /// Runs the daemon with the mock backend and returns the test and apply requests.
+    fn run_script(
+        name: &str,
+        script: MockScript,
+        settings: Settings,
+    ) -> Vec<(MockRequestKind, String, usize, MockResponse)> {
+        let socket = temp_path(&format!("{name}.socket"));
+        let backend = MockBackend::new(script);
+        let backend = run_with_backend(
+            backend,
+            settings,
+            Default::default(),
+            Some(socket.clone()),
+            insert_mock_sources,
+        )
+        .unwrap();
+        let _ = std::fs::remove_file(socket);
+        backend
+            .requests()
+            .iter()
+            .map(|r| (r.kind, r.profile.clone(), r.variant, r.response))
+            .collect()
+    }
+
+    #[test]
+    fn oneshot_applies_first_variant() {
+        use MockRequestKind::*;
+        use MockResponse::*;
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1"), head(1, "DP-1")],
+            ..Default::default()
+        };
+        let requests = run_script("oneshot", script, settings(true));
+        assert_eq!(
+            requests,
+            vec![
+                (Test, "desk".to_string(), 0, Succeeded),
+                (Apply, "desk".to_string(), 0, Succeeded),
+            ]
+        );
+    }
+
+    #[test]
+    fn scripted_responses() {
+        use MockRequestKind::*;
+        use MockResponse::*;
+        let rule = |variant, response, times| MockRule {
+            profile: "desk".to_string(),
+            variant,
+            response,
+            times,
+        };
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1"), head(1, "DP-1")],
+            responses: vec![rule(Some(0), Failed, None), rule(None, Failed, Some(1))],
+            ..Default::default()
+        };
+        let requests = run_script("responses", script, settings(true));
+        assert_eq!(
+            requests,
+            vec![
+                (Test, "desk".to_string(), 0, Failed),
+                (Test, "desk".to_string(), 1, Failed),
+            ]
+        );
+    }
+
+    #[test]
+    fn fail_once() {
+        use MockRequestKind::*;
+        use MockResponse::*;
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1"), head(1, "DP-1")],
+            responses: vec![MockRule {
+                profile: "desk".to_string(),
+                variant: None,
+                response: Failed,
+                times: Some(1),
+            }],
+            ..Default::default()
+        };
+        let requests = run_script("fail-once", script, settings(true));
+        assert_eq!(
+            requests,
+            vec![
+                (Test, "desk".to_string(), 0, Failed),
+                (Test, "desk".to_string(), 1, Succeeded),
+                (Apply, "desk".to_string(), 1, Succeeded),
+            ]
+        );
+    }
+
+    #[test]
+    fn hotplug_switches_profile() {
+        let step = |delay, event| MockHotplug { delay, event };
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1")],
+            hotplug: vec![
+                step(0, MockHotplugEvent::Connect(Box::new(head(1, "DP-1")))),
+                step(0, MockHotplugEvent::Disconnect("DP-1".to_string())),
+                step(0, MockHotplugEvent::Finish),
+            ],
+            ..Default::default()
+        };
+        let requests = run_script("hotplug", script, settings(false));
+        let applied: Vec<_> = requests
+            .into_iter()
+            .filter(|(kind, ..)| *kind == MockRequestKind::Apply)
+            .map(|(_, profile, ..)| profile)
+            .collect();
+        assert_eq!(applied, vec!["laptop", "desk", "laptop"]);
+    }
+
+    #[test]
+    fn revert_without_confirmation() {
+        use MockRequestKind::*;
+        use MockResponse::*;
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1"), head(1, "DP-1")],
+            ..Default::default()
+        };
+        let mut settings = settings(true);
+        settings.confirm_timeout = Some(Duration::ZERO);
+        let requests = run_script("revert", script, settings);
+        assert_eq!(
+            requests,
+            vec![
+                (Test, "desk".to_string(), 0, Succeeded),
+                (Apply, "desk".to_string(), 0, Succeeded),
+                (Apply, "snapshot".to_string(), 0, Succeeded),
+            ]
+        );
+    }
+
+    #[test]
+    fn replay_confirm_timeout() {
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1"), head(1, "DP-1")],
+            ..Default::default()
+        };
+        let (socket, recording) = (
+            temp_path("replay-confirm.socket"),
+            temp_path("replay-confirm.ron"),
+        );
+        let mut settings = settings(true);
+        settings.confirm_timeout = Some(Duration::ZERO);
+        let backend = RecordingBackend::new(MockBackend::new(script), &recording, &settings, &[]);
+        run_with_backend(
+            backend.unwrap(),
+            settings,
+            Default::default(),
+            Some(socket.clone()),
+            insert_mock_sources,
+        )
+        .unwrap();
+        let _ = std::fs::remove_file(socket);
+        let content = std::fs::read_to_string(&recording).unwrap();
+        assert!(content.contains("ConfirmTimeout(1)"));
+        // the snapshot is applied again
+        assert!(replay::replay(&recording).unwrap());
+        let _ = std::fs::remove_file(recording);
+    }
+
+    #[test]
+    fn replay_recording() {
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1")],
+            responses: vec![MockRule {
+                profile: "desk".to_string(),
+                variant: Some(0),
+                response: MockResponse::Failed,
+                times: None,
+            }],
+            hotplug: vec![
+                MockHotplug {
+                    delay: 0,
+                    event: MockHotplugEvent::Connect(Box::new(head(1, "DP-1"))),
+                },
+                MockHotplug {
+                    delay: 0,
+                    event: MockHotplugEvent::Finish,
+                },
+            ],
+        };
+        let (socket, recording) = (temp_path("replay.socket"), temp_path("replay.ron"));
+        let settings = settings(false);
+        let backend = RecordingBackend::new(MockBackend::new(script), &recording, &settings, &[]);
+        run_with_backend(
+            backend.unwrap(),
+            settings,
+            Default::default(),
+            Some(socket.clone()),
+            insert_mock_sources,
+        )
+        .unwrap();
+        let _ = std::fs::remove_file(socket);
+        assert!(replay::replay(&recording).unwrap());
+
+        // a recording without the first test request does not match anymore
+        let content = std::fs::read_to_string(&recording).unwrap();
+        let first_test = content.lines().position(|l| l.contains("Test(")).unwrap();
+        let tampered: Vec<_> = content
+            .lines()
+            .enumerate()
+            .filter(|(idx, _)| *idx != first_test)
+            .map(|(_, line)| line)
+            .collect();
+        std::fs::write(&recording, tampered.join("\n")).unwrap();
+        assert!(!replay::replay(&recording).unwrap());
+        let _ = std::fs::remove_file(recording);
+    }
+
+    #[test]
+    fn replay_with_pins() {
+        let heads = vec![head(0, "eDP-1"), head(1, "DP-1")];
+        let pin = PersistedPin {
+            heads: head_set_key(&heads.clone().into()),
+            profile: "extended".to_string(),
+        };
+        let pin_store = PinStore::from_pins(vec![pin]);
+        let script = MockScript {
+            heads,
+            ..Default::default()
+        };
+        let (socket, recording) = (
+            temp_path("replay-pins.socket"),
+            temp_path("replay-pins.ron"),
+        );
+        let settings = extended_settings(true);
+        let backend = RecordingBackend::new(
+            MockBackend::new(script),
+            &recording,
+            &settings,
+            pin_store.pins(),
+        );
+        let mut backend = run_with_backend(
+            backend.unwrap(),
+            settings,
+            pin_store,
+            Some(socket.clone()),
+            insert_mock_sources,
+        )
+        .unwrap();
+        let _ = std::fs::remove_file(socket);
+        let requests = backend.as_mut().requests();
+        assert!(!requests.is_empty());
+        assert!(requests.iter().all(|r| r.profile == "extended"));
+        // the pinned profile is applied again instead of the best ranked one
+        assert!(replay::replay(&recording).unwrap());
+        let _ = std::fs::remove_file(recording);
+    }
+
+    #[test]
+    fn replay_ipc_switch() {
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1"), head(1, "DP-1")],
+            ..Default::default()
+        };
+        let settings = extended_settings(false);
+        let recording = temp_path("replay-ipc.ron");
+        let backend = RecordingBackend::new(MockBackend::new(script), &recording, &settings, &[]);
+        let mut dsm = DaemonStateMachine::new(backend.unwrap(), settings);
+        settle(&mut dsm);
+        let request = IpcRequest::SwitchProfile {
+            name: "extended".to_string(),
+            pin: false,
+            persist: false,
+        };
+        delegate_command(request, &mut dsm);
+        settle(&mut dsm);
+
+        let applied: Vec<_> = dsm
+            .backend
+            .as_mut()
+            .requests()
+            .iter()
+            .filter(|r| r.kind == MockRequestKind::Apply)
+            .map(|r| r.profile.clone())
+            .collect();
+        assert_eq!(applied, vec!["desk", "extended"]);
+        drop(dsm);
+        assert!(replay::replay(&recording).unwrap());
+        let _ = std::fs::remove_file(recording);
+    }
+
+    #[test]
+    fn cycle_profiles_with_identical_outputs() {
+        let mut settings = extended_settings(false);
+        let mut copy = settings.profiles.back().cloned().unwrap();
+        copy.name = "extended copy".to_string();
+        copy.index = settings.profiles.len();
+        settings.profiles.push_back(copy);
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1"), head(1, "DP-1")],
+            ..Default::default()
+        };
+        let recording = temp_path("cycle-identical.ron");
+        let backend = RecordingBackend::new(MockBackend::new(script), &recording, &settings, &[]);
+        let mut dsm = DaemonStateMachine::new(backend.unwrap(), settings);
+        // the answer waits until the worker pool has matched the heads
+        assert!(delegate_command(IpcRequest::NextProfile, &mut dsm).is_none());
+        settle(&mut dsm);
+
+        let mut applied = vec![];
+        for _ in 0..4 {
+            let DSMState::VariantApplied(variant) = dsm.state() else {
+                panic!("no variant has been applied");
+            };
+            applied.push(variant.profile.name.clone());
+            let Some(IpcResponse::Generic(text)) =
+                delegate_command(IpcRequest::NextProfile, &mut dsm)
+            else {
+                panic!("the request has not been answered");
+            };
+            assert!(delegate_command(IpcRequest::NextProfile, &mut dsm).is_none());
+            settle(&mut dsm);
+            let DSMState::VariantApplied(variant) = dsm.state() else {
+                panic!("no variant has been applied");
+            };
+            assert_eq!(text, format!("Switching to {:?}", variant.profile.name));
+        }
+        // both profiles with the same outputs are cycled through
+        assert_eq!(applied[3], applied[0]);
+        applied.truncate(3);
+        applied.sort();
+        assert_eq!(applied, vec!["desk", "extended", "extended copy"]);
+        drop(dsm);
+        assert!(replay::replay(&recording).unwrap());
+        let _ = std::fs::remove_file(recording);
+    }
+
+    #[test]
+    fn switch_to_listed_variant() {
+        let mut settings = settings(false);
+        let side_by_side: SettingsToml = toml::from_str(
+            r#"
+            [[profile]]
+            name = "side by side"
+            output = [
+                { search = "/DP", enable = true, mode = "1920x1080", position = "0,0" },
+                { search = "/DP", enable = true, mode = "1920x1080", position = "1920,0" },
+            ]
+            "#,
+        )
+        .unwrap();
+        for mut profile in side_by_side.profiles {
+            profile.index = settings.profiles.len();
+            settings.profiles.push_back(profile);
+        }
+        let script = MockScript {
+            heads: vec![head(0, "eDP-1"), head(1, "DP-1")],
+            ..Default::default()
+        };
+        let mut dsm = DaemonStateMachine::new(MockBackend::new(script), settings);
+        settle(&mut dsm);
+
+        let Some(IpcResponse::MatchReports(reports)) =
+            delegate_command(IpcRequest::MatchReports, &mut dsm)
+        else {
+            panic!("no match reports");
+        };
+        // both outputs fit both heads, the variants of both subsets are listed
+        let listed: Vec<_> = ProfileManager::rank_variants(&reports)
+            .filter(|v| v.profile.name == "side by side")
+            .collect();
+        assert_eq!(listed.len(), 8);
+        for variant in listed {
+            let request = IpcRequest::SwitchVariant {
+                profile: variant.profile.index,
+                variant: variant.index,
+            };
+            assert!(matches!(
+                delegate_command(request, &mut dsm),
+                Some(IpcResponse::Success)
+            ));
+            settle(&mut dsm);
+            let DSMState::VariantApplied(applied) = dsm.state() else {
+                panic!("variant {} has not been applied", variant.idx_str());
+            };
+            assert_eq!(applied.idx_str(), variant.idx_str());
+            assert_eq!(applied.pairings, variant.pairings);
+        }
+    }
+}
@@ -9,8 +9,9 @@ use snafu::prelude::*;
 use crate::daemon::InsertCtx;
 use crate::error;
 use crate::ipc::{IpcRequest, IpcResponse, IpcStream, SocketAcceptCtx};
+use crate::matching::ProfileMatcher;
 use crate::search::SearchPattern;
-use crate::wl_backend::WlBackend;
+use crate::wl_backend::{RecordEntry, WlBackend};
 
 use super::profile_manager::{ProfileManager, Restriction};
 use super::state_machine::{DSMState, DaemonStateMachine};
@@ -31,10 +32,12 @@ pub fn handle_listener(
         .insert_source(stream_event_source, |_, stream, shikane| {
             // this is safe because the inner stream does not get dropped
             // also this source gets immediately removed at the end of this closure
-            if let Err(err) = handle_client(unsafe { stream.get_mut() }, &mut shikane.dsm) {
+            if let Err(err) = handle_client(unsafe { stream.get_mut() }, shikane) {
                 let err = error::report(err.as_ref());
                 warn!("IPC error({})", err);
             }
+            // e.g. a confirmation in oneshot mode
+            shikane.state_machine_advanced();
             Ok(calloop::PostAction::Remove)
         })
         .context(InsertCtx)?;
@@ -43,21 +46,70 @@ pub fn handle_listener(
 
 fn handle_client(
     ipc: &mut IpcStream,
-    state: &mut Dsm<impl WlBackend>,
+    shikane: &mut Shikane<impl WlBackend>,
 ) -> Result<(), Box<dyn snafu::Error>> {
     let request: IpcRequest = ipc.recv()?;
-    let response = delegate_command(request, state);
-    ipc.send(&response)?;
+    match delegate_command(request.clone(), &mut shikane.dsm) {
+        Some(response) => ipc.send(&response)?,
+        // the event source of the stream is removed, a duplicate is kept to answer later
+        None => shikane.deferred_requests.push((ipc.try_clone()?, request)),
+    }
     Ok(())
 }
 
-fn delegate_command(command: IpcRequest, state: &mut Dsm<impl WlBackend>) -> IpcResponse {
-    match command {
+/// Delegates the deferred requests again, e.g. after reports of the worker pool have been
+/// received. Requests that still have to wait are kept.
+pub fn answer_deferred_requests(shikane: &mut Shikane<impl WlBackend>) {
+    for (mut ipc, request) in std::mem::take(&mut shikane.deferred_requests) {
+        match delegate_command(request.clone(), &mut shikane.dsm) {
+            Some(response) => {
+                if let Err(err) = ipc.send(&response) {
+                    warn!("IPC error({})", error::report(&err));
+                }
+            }
+            None => shikane.deferred_requests.push((ipc, request)),
+        }
+    }
+}
+
+/// Handles the request, which is recorded first if the daemon is recording.
+///
+/// Returns [`None`] if the answer depends on reports that are still awaited from the worker
+/// pool. The request is to be delegated again after they have been received.
+pub(crate) fn delegate_command(
+    command: IpcRequest,
+    state: &mut Dsm<impl WlBackend>,
+) -> Option<IpcResponse> {
+    state
+        .backend
+        .record_input(RecordEntry::Request(command.clone()));
+    let response = match command {
+        IpcRequest::Confirm => req_confirm(state),
         IpcRequest::CurrentHeads => req_current_heads(state),
         IpcRequest::CurrentState => req_current_variant(state),
+        IpcRequest::Explain(pname) => req_explain(state, pname),
         IpcRequest::MatchReports => req_match_reports(state),
+        IpcRequest::NextProfile => return req_cycle_profile(state, true),
+        IpcRequest::Pause => req_pause(state),
+        IpcRequest::PreviousProfile => return req_cycle_profile(state, false),
         IpcRequest::ReloadConfig(path) => req_reload_config(state, path),
-        IpcRequest::SwitchProfile(pname) => req_switch_profile(state, pname),
+        IpcRequest::Resume => req_resume(state),
+        IpcRequest::Revert => req_revert(state),
+        IpcRequest::SwitchProfile { name, pin, persist } => {
+            req_switch_profile(state, name, pin, persist)
+        }
+        IpcRequest::SwitchVariant { profile, variant } => {
+            req_switch_variant(state, profile, variant)
+        }
+        IpcRequest::Unpin => req_unpin(state),
+    };
+    Some(response)
+}
+
+fn req_confirm(state: &mut Dsm<impl WlBackend>) -> IpcResponse {
+    match state.confirm() {
+        true => IpcResponse::Success,
+        false => IpcResponse::Error("No profile is waiting for confirmation".to_string()),
     }
 }
 
@@ -69,12 +121,79 @@ fn req_current_heads(state: &Dsm<impl WlBackend>) -> IpcResponse {
 }
 
 fn req_current_variant(state: &Dsm<impl WlBackend>) -> IpcResponse {
-    match state.state() {
-        s @ DSMState::VariantApplied(v) | s @ DSMState::VariantInProgress(v) => {
-            IpcResponse::Generic(format!("{s}: {}", v.profile.name))
+    let text = match state.state() {
+        s @ DSMState::VariantApplied(v)
+        | s @ DSMState::VariantInProgress(v)
+        | s @ DSMState::AwaitingConfirmation(v)
+        | s @ DSMState::Reverting(Some(v)) => {
+            format!("{s}: {}", v.profile.name)
         }
-        s => IpcResponse::Generic(format!("{s}")),
+        s => format!("{s}"),
+    };
+    let mut prefixes = vec![];
+    // nothing has been applied for real
+    if state.settings.dry_run {
+        prefixes.push("dry run, ");
     }
+    if state.is_paused() {
+        prefixes.push("paused, ");
+    }
+    IpcResponse::Generic(format!("{}{text}", prefixes.concat()))
+}
+
+fn req_cycle_profile(state: &mut Dsm<impl WlBackend>, forward: bool) -> Option<IpcResponse> {
+    let Some(heads) = state.backend.export_heads() else {
+        return Some(IpcResponse::Error("no heads available".to_string()));
+    };
+    let Some(profiles) = state.pm.ranked_profiles(&heads) else {
+        state.pm.rank_profiles(heads);
+        return None;
+    };
+    if profiles.is_empty() {
+        let text = "No profile fits the connected displays".to_string();
+        return Some(IpcResponse::Error(text));
+    }
+    let current = match state.state() {
+        DSMState::VariantInProgress(v)
+        | DSMState::VariantApplied(v)
+        | DSMState::AwaitingConfirmation(v) => profiles.iter().position(|p| *p == v.profile.name),
+        _ => None,
+    };
+    let len = profiles.len();
+    let idx = match (current, forward) {
+        (Some(idx), true) => (idx + 1) % len,
+        (Some(idx), false) => (idx + len - 1) % len,
+        (None, true) => 0,
+        (None, false) => len - 1,
+    };
+    let profile_name = profiles[idx].clone();
+    state
+        .pm
+        .restrict(SearchPattern::Fulltext(profile_name.clone()).into());
+    state.simulate_switch();
+    // the variants of the profile are still to be matched and tried
+    Some(IpcResponse::Generic(format!(
+        "Switching to {profile_name:?}"
+    )))
+}
+
+fn req_explain(state: &Dsm<impl WlBackend>, profile_name: Option<String>) -> IpcResponse {
+    let Some(heads) = state.backend.export_heads() else {
+        return IpcResponse::Error("no heads available".to_string());
+    };
+    let explanations: Vec<_> = state
+        .settings
+        .profiles
+        .iter()
+        .filter(|p| profile_name.as_ref().map_or(true, |name| p.name == *name))
+        .map(|p| ProfileMatcher::explain(p, &heads))
+        .collect();
+    if explanations.is_empty() {
+        if let Some(name) = profile_name {
+            return IpcResponse::Error(format!("No profile named {name:?}"));
+        }
+    }
+    IpcResponse::Explanations(explanations)
 }
 
 fn req_match_reports(state: &Dsm<impl WlBackend>) -> IpcResponse {
@@ -82,21 +201,140 @@ fn req_match_reports(state: &Dsm<impl WlBackend>) -> IpcResponse {
     IpcResponse::MatchReports(reports)
 }
 
+fn req_pause(state: &mut Dsm<impl WlBackend>) -> IpcResponse {
+    match state.pause() {
+        true => IpcResponse::Success,
+        false => IpcResponse::Error("The daemon is already paused".to_string()),
+    }
+}
+
+fn req_resume(state: &mut Dsm<impl WlBackend>) -> IpcResponse {
+    match state.resume() {
+        true => IpcResponse::Success,
+        false => IpcResponse::Error("The daemon is not paused".to_string()),
+    }
+}
+
 fn req_reload_config(state: &mut Dsm<impl WlBackend>, path: Option<PathBuf>) -> IpcResponse {
-    if let Err(err) = state.settings.reload_config(path) {
+    let mut settings = state.settings.clone();
+    if let Err(err) = settings.reload_config(path) {
         return IpcResponse::Error(error::report(err.as_ref()).to_string());
     }
-    state.pm = ProfileManager::new(state.settings.profiles.clone());
-    state.simulate_change();
+    state
+        .backend
+        .record_input(RecordEntry::Reload(settings.clone()));
+    state.reload_settings(settings);
     IpcResponse::Success
 }
 
-fn req_switch_profile(state: &mut Dsm<impl WlBackend>, profile_name: String) -> IpcResponse {
+fn req_revert(state: &mut Dsm<impl WlBackend>) -> IpcResponse {
+    match state.revert_to_previous() {
+        Ok(()) => IpcResponse::Success,
+        Err(err) => IpcResponse::Error(error::report(&err)),
+    }
+}
+
+fn req_switch_profile(
+    state: &mut Dsm<impl WlBackend>,
+    search: String,
+    pin: bool,
+    persist: bool,
+) -> IpcResponse {
+    let profile_name = match find_profile(state, &search) {
+        Ok(profile_name) => profile_name,
+        Err(err) => return IpcResponse::Error(err),
+    };
     let restriction: Restriction = SearchPattern::Fulltext(profile_name.clone()).into();
-    if !state.pm.test_restriction(&restriction) {
-        return IpcResponse::Error(format!("No matching profile found {:?}", profile_name));
+    match pin {
+        true => {
+            let heads = match persist {
+                true => match state.backend.export_heads() {
+                    Some(heads) => Some(heads),
+                    None => return IpcResponse::Error("no heads available".to_string()),
+                },
+                false => None,
+            };
+            if let Err(err) = state.pm.pin(profile_name, heads.as_ref()) {
+                return IpcResponse::Error(error::report(&err));
+            }
+        }
+        false => state.pm.restrict(restriction),
     }
-    state.pm.restrict(restriction);
-    state.simulate_change();
+    state.simulate_switch();
     IpcResponse::Success
 }
+
+/// Returns the name of the only profile matching the search, a profile with exactly this name
+/// takes precedence. Lists the candidates with their rank if several profiles match.
+fn find_profile(state: &Dsm<impl WlBackend>, search: &str) -> Result<String, String> {
+    let exact: Restriction = SearchPattern::Fulltext(search.to_string()).into();
+    if !state.pm.matching_profiles(&exact).is_empty() {
+        return Ok(search.to_string());
+    }
+    let restriction = Restriction::parse(search)
+        .map_err(|err| format!("Invalid search {search:?}: {}", error::report(&err)))?;
+    let mut candidates = state.pm.matching_profiles(&restriction);
+    match candidates.len() {
+        0 => return Err(format!("No matching profile found {search:?}")),
+        1 => return Ok(candidates.remove(0)),
+        _ => {}
+    }
+
+    // the candidates are not ranked while the heads are being matched
+    let ranked = match state.backend.export_heads() {
+        Some(heads) => state.pm.ranked_profiles(&heads).unwrap_or_default(),
+        None => vec![],
+    };
+    let rank = |name: &String| ranked.iter().position(|r| r == name);
+    // candidates that do not fit the heads come last
+    candidates.sort_by_key(|name| rank(name).unwrap_or(usize::MAX));
+    let lines: Vec<String> = candidates
+        .iter()
+        .map(|name| match rank(name) {
+            Some(rank) => format!("  {}. {name:?}", rank + 1),
+            None => format!("  -. {name:?} (not ranked)"),
+        })
+        .collect();
+    Err(format!(
+        "Several profiles match {search:?}, use a more specific search:\n{}",
+        lines.join("\n")
+    ))
+}
+
+fn req_switch_variant(
+    state: &mut Dsm<impl WlBackend>,
+    profile_index: usize,
+    index: usize,
+) -> IpcResponse {
+    let Some(heads) = state.backend.export_heads() else {
+        return IpcResponse::Error("no heads available".to_string());
+    };
+    // the indices refer to the variants generated for the heads at that time
+    if state.pm.is_cache_outdated(&heads) {
+        return IpcResponse::Error(
+            "The displays changed since the variants were generated, list them again".to_string(),
+        );
+    }
+    let variants = ProfileManager::rank_variants(state.pm.reports());
+    let Some(variant) = variants.find(profile_index, index) else {
+        return IpcResponse::Error(format!("No variant {profile_index},{index} found"));
+    };
+    match state.switch_to_variant(variant) {
+        true => IpcResponse::Success,
+        false => IpcResponse::Error(format!("Cannot switch at state {}", state.state())),
+    }
+}
+
+fn req_unpin(state: &mut Dsm<impl WlBackend>) -> IpcResponse {
+    let Some(heads) = state.backend.export_heads() else {
+        return IpcResponse::Error("no heads available".to_string());
+    };
+    match state.pm.unpin(&heads) {
+        Ok(true) => {
+            state.simulate_change();
+            IpcResponse::Success
+        }
+        Ok(false) => IpcResponse::Error("No profile is pinned".to_string()),
+        Err(err) => IpcResponse::Error(error::report(&err)),
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use calloop::channel::{self, Channel, Sender};
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::matching::{MatchReport, ProfileMatcher};
+use crate::profile::Profile;
+use crate::wl_backend::WlHead;
+
+/// Upper bound for the threads matching profiles at the same time.
+const MAX_MATCHING_WORKERS: usize = 8;
+
+/// Matches profiles against the heads on worker threads that live as long as the pool.
+///
+/// Only the newest job is worked on. Submitting a job drops the profiles of the previous one that
+/// have not been picked up by a worker yet, so a burst of head changes cannot pile up matching
+/// jobs. The reports of a job are sent through a calloop channel once all of its profiles are
+/// matched.
+pub struct MatchPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    channel: Option<Channel<MatchResult>>,
+    generation: u64,
+}
+
+/// The reports of a job, in the order of its profiles.
+#[derive(Debug)]
+pub struct MatchResult {
+    pub generation: u64,
+    pub reports: Vec<MatchReport>,
+}
+
+struct Shared {
+    state: Mutex<PoolState>,
+    job_submitted: Condvar,
+}
+
+struct PoolState {
+    job: Option<Arc<Job>>,
+    // index of the next profile a worker picks up
+    next_profile: usize,
+    matched_profiles: usize,
+    reports: Vec<(usize, MatchReport)>,
+    sender: Sender<MatchResult>,
+    shutdown: bool,
+}
+
+struct Job {
+    generation: u64,
+    profiles: Vec<Profile>,
+    wl_heads: VecDeque<WlHead>,
+}
+
+impl MatchPool {
+    pub fn new() -> Self {
+        let (sender, channel) = channel::channel();
+        let state = PoolState {
+            job: None,
+            next_profile: 0,
+            matched_profiles: 0,
+            reports: vec![],
+            sender,
+            shutdown: false,
+        };
+        let shared = Arc::new(Shared {
+            state: Mutex::new(state),
+            job_submitted: Condvar::new(),
+        });
+        let workers = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(MAX_MATCHING_WORKERS);
+        trace!("starting {workers} matching workers");
+        let workers = (0..workers)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || shared.work())
+            })
+            .collect();
+        Self {
+            shared,
+            workers,
+            channel: Some(channel),
+            generation: 0,
+        }
+    }
+
+    /// Replaces the current job. Returns the generation the result of this job will carry.
+    pub fn submit(&mut self, profiles: Vec<Profile>, wl_heads: VecDeque<WlHead>) -> u64 {
+        self.generation += 1;
+        let generation = self.generation;
+        let mut state = self.shared.lock();
+        state.next_profile = 0;
+        state.matched_profiles = 0;
+        state.reports.clear();
+        if profiles.is_empty() {
+            state.job = None;
+            state.send(generation, vec![]);
+            return generation;
+        }
+        trace!("matching {} profiles, job {generation}", profiles.len());
+        state.job = Some(Arc::new(Job {
+            generation,
+            profiles,
+            wl_heads,
+        }));
+        self.shared.job_submitted.notify_all();
+        generation
+    }
+
+    /// Hands the channel with the results over, e.g. to insert it into an event loop.
+    pub fn take_channel(&mut self) -> Option<Channel<MatchResult>> {
+        self.channel.take()
+    }
+
+    /// Blocks until the next result arrives. Returns `None` if the channel has been taken.
+    pub fn recv(&self) -> Option<MatchResult> {
+        self.channel.as_ref()?.recv().ok()
+    }
+}
+
+impl Default for MatchPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MatchPool {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.job_submitted.notify_all();
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                error!("A matching worker panicked");
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for MatchPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MatchPool")
+            .field("workers", &self.workers.len())
+            .field("generation", &self.generation)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn work(&self) {
+        let mut state = self.lock();
+        loop {
+            if state.shutdown {
+                return;
+            }
+            let job = match &state.job {
+                Some(job) if state.next_profile < job.profiles.len() => Arc::clone(job),
+                _ => {
+                    state = self
+                        .job_submitted
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                    continue;
+                }
+            };
+            let idx = state.next_profile;
+            state.next_profile += 1;
+            drop(state);
+
+            let report = create_report(&job.profiles[idx], &job.wl_heads);
+
+            state = self.lock();
+            // a newer job has been submitted in the meantime
+            if !state.job.as_ref().is_some_and(|j| Arc::ptr_eq(j, &job)) {
+                continue;
+            }
+            state.matched_profiles += 1;
+            if let Some(report) = report {
+                state.reports.push((idx, report));
+            }
+            if state.matched_profiles == job.profiles.len() {
+                state.job = None;
+                let mut reports = std::mem::take(&mut state.reports);
+                reports.sort_by_key(|(idx, _)| *idx);
+                let reports = reports.into_iter().map(|(_, report)| report).collect();
+                state.send(job.generation, reports);
+            }
+        }
+    }
+}
+
+impl PoolState {
+    fn send(&self, generation: u64, reports: Vec<MatchReport>) {
+        let result = MatchResult {
+            generation,
+            reports,
+        };
+        if self.sender.send(result).is_err() {
+            debug!("Dropping the reports of job {generation}, nobody is waiting for them");
+        }
+    }
+}
+
+/// A profile that cannot be matched because of a bug does not take the worker down.
+fn create_report(profile: &Profile, wl_heads: &VecDeque<WlHead>) -> Option<MatchReport> {
+    debug!(
+        "len(heads,outputs)=({},{}) profile.name={:?}",
+        wl_heads.len(),
+        profile.outputs.len(),
+        profile.name
+    );
+    let create = || ProfileMatcher::create_report(profile.clone(), wl_heads.clone());
+    std::panic::catch_unwind(AssertUnwindSafe(create)).unwrap_or_else(|_| {
+        error!("Matching the profile {:?} panicked", profile.name);
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::settings::SettingsToml;
+    use crate::wl_backend::test_heads::dp_heads;
+
+    use super::MatchPool;
+
+    #[test]
+    fn newer_job_replaces_pending_one() {
+        let heads = dp_heads(2);
+        let config: String = (0..40)
+            .map(|idx| {
+                let outputs =
+                    r#"{ search = "n=DP-0", enable = true }, { search = "/DP", enable = true }"#;
+                format!("[[profile]]\nname = \"{idx}\"\noutput = [{outputs}]\n")
+            })
+            .collect();
+        let config: SettingsToml = toml::from_str(&config).unwrap();
+        let profiles: Vec<_> = config.profiles.into_iter().collect();
+
+        let mut pool = MatchPool::new();
+        let first = pool.submit(profiles.clone(), heads.clone());
+        let second = pool.submit(profiles[..3].to_vec(), heads);
+        assert!(first < second);
+        // the first job has either been finished before or been dropped
+        let result = loop {
+            let result = pool.recv().unwrap();
+            if result.generation == second {
+                break result;
+            }
+            assert_eq!(result.generation, first);
+        };
+        let names: Vec<_> = result.reports.iter().map(|r| &r.profile.name).collect();
+        assert_eq!(names, ["0", "1", "2"]);
+
+        assert!(pool.take_channel().is_some());
+        assert!(pool.recv().is_none());
+    }
+}
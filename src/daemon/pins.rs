@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use snafu::{prelude::*, Location};
+
+use crate::wl_backend::{head_set_key, WlHead};
+
+const PINS_FILE: &str = "pins.ron";
+
+/// Profiles pinned with `shikanectl switch --pin --persist`, keyed by the set of connected heads.
+#[derive(Debug, Default)]
+pub struct PinStore {
+    /// File the pins are saved to, pins without a file are only kept in memory
+    path: Option<PathBuf>,
+    pins: Vec<PersistedPin>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedPin {
+    /// Make, model, serial number and name of each head, sorted
+    pub heads: Vec<String>,
+    pub profile: String,
+}
+
+impl PinStore {
+    /// Loads the pins from `$XDG_STATE_HOME/shikane/pins.ron`.
+    pub fn load() -> Result<Self, PinError> {
+        let xdg_dirs = xdg::BaseDirectories::with_prefix("shikane").context(BaseDirectoriesCtx)?;
+        Self::from_file(xdg_dirs.get_state_home().join(PINS_FILE))
+    }
+
+    /// Loads the pins from the file, a file that does not exist yet contains no pins.
+    pub fn from_file(path: PathBuf) -> Result<Self, PinError> {
+        let pins = match path.exists() {
+            true => {
+                let content =
+                    std::fs::read_to_string(&path).context(ReadPinsCtx { path: &path })?;
+                ron::de::from_str(&content).context(ParsePinsCtx)?
+            }
+            false => vec![],
+        };
+        debug!("Loaded {} pinned profile(s) from {path:?}", pins.len());
+        let path = Some(path);
+        Ok(Self { path, pins })
+    }
+
+    /// Keeps these pins in memory only, e.g. for replaying a recording.
+    pub fn from_pins(pins: Vec<PersistedPin>) -> Self {
+        Self { path: None, pins }
+    }
+
+    pub fn pins(&self) -> &[PersistedPin] {
+        &self.pins
+    }
+
+    /// Returns the profile pinned for exactly these heads.
+    pub fn get(&self, heads: &VecDeque<WlHead>) -> Option<&str> {
+        let key = head_set_key(heads);
+        self.pins
+            .iter()
+            .find(|pin| pin.heads == key)
+            .map(|pin| pin.profile.as_str())
+    }
+
+    /// Pins the profile for these heads, replacing an older pin.
+    pub fn insert(&mut self, heads: &VecDeque<WlHead>, profile: String) -> Result<(), PinError> {
+        let key = head_set_key(heads);
+        self.pins.retain(|pin| pin.heads != key);
+        self.pins.push(PersistedPin {
+            heads: key,
+            profile,
+        });
+        self.save()
+    }
+
+    /// Removes the pin for these heads. Returns false if there was none.
+    pub fn remove(&mut self, heads: &VecDeque<WlHead>) -> Result<bool, PinError> {
+        let key = head_set_key(heads);
+        let len = self.pins.len();
+        self.pins.retain(|pin| pin.heads != key);
+        if self.pins.len() == len {
+            return Ok(false);
+        }
+        self.save().map(|_| true)
+    }
+
+    fn save(&self) -> Result<(), PinError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(WritePinsCtx { path })?;
+        }
+        let content =
+            ron::ser::to_string_pretty(&self.pins, Default::default()).context(SerializePinsCtx)?;
+        std::fs::write(path, content).context(WritePinsCtx { path })
+    }
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(context(suffix(Ctx)))]
+#[snafu(visibility(pub(crate)))]
+pub enum PinError {
+    #[snafu(display("[{location}] Problem with XDG directories"))]
+    BaseDirectories {
+        source: xdg::BaseDirectoriesError,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot read pinned profiles from {path:?}"))]
+    ReadPins {
+        source: std::io::Error,
+        location: Location,
+        path: PathBuf,
+    },
+    #[snafu(display("[{location}] Cannot parse pinned profiles"))]
+    ParsePins {
+        source: ron::error::SpannedError,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot serialize pinned profiles"))]
+    SerializePins {
+        source: ron::Error,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot write pinned profiles to {path:?}"))]
+    WritePins {
+        source: std::io::Error,
+        location: Location,
+        path: PathBuf,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::wl_backend::test_heads::head_with_base;
+    use crate::wl_backend::{WlBaseHead, WlHead};
+
+    use super::PinStore;
+
+    fn heads(names: &[&str]) -> VecDeque<WlHead> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| {
+                let base = WlBaseHead {
+                    name: name.to_string(),
+                    make: "Dell".to_string(),
+                    ..Default::default()
+                };
+                head_with_base(id, base, &[])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn persist_pins() {
+        let path = std::env::temp_dir()
+            .join(format!("shikane-test-{}", std::process::id()))
+            .join("pins.ron");
+        let _ = std::fs::remove_file(&path);
+        let mut store = PinStore::from_file(path.clone()).unwrap();
+        store
+            .insert(&heads(&["DP-1", "eDP-1"]), "desk".to_string())
+            .unwrap();
+        store
+            .insert(&heads(&["eDP-1"]), "laptop".to_string())
+            .unwrap();
+        store
+            .insert(&heads(&["eDP-1"]), "mobile".to_string())
+            .unwrap();
+
+        let mut store = PinStore::from_file(path.clone()).unwrap();
+        // the order of the heads does not matter
+        assert_eq!(store.get(&heads(&["eDP-1", "DP-1"])), Some("desk"));
+        assert_eq!(store.get(&heads(&["eDP-1"])), Some("mobile"));
+        assert_eq!(store.get(&heads(&["DP-1"])), None);
+
+        assert!(store.remove(&heads(&["eDP-1"])).unwrap());
+        assert!(!store.remove(&heads(&["eDP-1"])).unwrap());
+        let store = PinStore::from_file(path.clone()).unwrap();
+        assert_eq!(store.get(&heads(&["eDP-1"])), None);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -3,24 +3,51 @@ use std::collections::{HashSet, VecDeque};
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 
-use crate::matching::{MatchReport, ProfileMatcher};
+use crate::matching::{MatchReport, RankedVariants};
 use crate::profile::Profile;
-use crate::search::SearchPattern;
+use crate::search::{ParseSingleSearchError, SearchPattern, SingleSearch};
 use crate::variant::ValidVariant;
 use crate::wl_backend::{LessEqWlHead, WlHead};
 
+use super::match_pool::{MatchPool, MatchResult};
+use super::pins::{PinError, PinStore};
+
 #[derive(Debug)]
 pub struct ProfileManager {
     profiles: VecDeque<Profile>,
-    variants: VecDeque<ValidVariant>,
+    variants: RankedVariants,
     reports: VecDeque<MatchReport>,
+    // reports of all profiles fitting the ranked heads, regardless of restrictions and pins
+    unrestricted_reports: VecDeque<MatchReport>,
+    // the heads of the unrestricted reports, none if they have not been matched yet
+    ranked_heads: Option<VecDeque<WlHead>>,
     restriction: Option<Restriction>,
+    // restriction that is kept until it is unpinned
+    pin: Option<Restriction>,
+    pin_store: PinStore,
     cached_heads: VecDeque<WlHead>,
+    max_variants: Option<usize>,
+    pool: MatchPool,
+    // the job whose reports are awaited
+    pending: Option<PendingJob>,
+}
+
+/// Which of the reports of a submitted job are used.
+#[derive(Debug)]
+struct PendingJob {
+    generation: u64,
+    heads: VecDeque<WlHead>,
+    // only the profiles are ranked, the variants are kept
+    ranking_only: bool,
+    restriction: Option<Restriction>,
+    // all reports are used if none fits the pinned profile
+    pinned: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct Restriction {
     pattern: SearchPattern,
+    negated: bool,
 }
 
 impl ProfileManager {
@@ -29,19 +56,54 @@ impl ProfileManager {
             profiles,
             variants: Default::default(),
             reports: Default::default(),
+            unrestricted_reports: Default::default(),
+            ranked_heads: None,
             restriction: Default::default(),
+            pin: Default::default(),
+            pin_store: Default::default(),
             cached_heads: Default::default(),
+            max_variants: Default::default(),
+            pool: Default::default(),
+            pending: None,
         }
     }
     pub fn set_profiles(&mut self, profiles: VecDeque<Profile>) {
-        self.profiles = profiles
+        self.profiles = profiles;
+        self.ranked_heads = None;
+    }
+    /// Limit how many variants are tried after each change of heads.
+    pub fn set_max_variants(&mut self, max_variants: Option<usize>) {
+        self.max_variants = max_variants
     }
     pub fn reports(&self) -> &VecDeque<MatchReport> {
         &self.reports
     }
 
+    /// Build the next best variant.
     pub fn next_variant(&mut self) -> Option<ValidVariant> {
-        self.variants.pop_front()
+        let duplicates = self.variants.duplicate_count();
+        let variant = self.variants.next();
+        let skipped = self.variants.duplicate_count() - duplicates;
+        if skipped > 0 {
+            debug!(
+                "skipped {skipped} duplicate variants ({} in total)",
+                self.variants.duplicate_count()
+            );
+        }
+        if variant.is_none() && self.variants.is_limit_reached() {
+            info!(
+                "Reached the limit of {} variants, ignoring the remaining ones",
+                self.max_variants.unwrap_or_default()
+            );
+        }
+        let variant = variant?;
+        trace!(
+            "{}:(specificity, deviation):({}, {})",
+            variant.idx_str(),
+            variant.specificity(),
+            variant.mode_deviation()
+        );
+        Some(variant)
     }
     /// Restrict profile selection to a profile with a name that matches the restriction.
     pub fn restrict(&mut self, rest: Restriction) {
@@ -51,82 +113,213 @@ impl ProfileManager {
     pub fn lift_restriction(&mut self) -> Option<Restriction> {
         self.restriction.take()
     }
-    pub fn test_restriction(&self, rest: &Restriction) -> bool {
+    /// Persisted pins are loaded from and saved to this store.
+    pub fn set_pin_store(&mut self, pin_store: PinStore) {
+        self.pin_store = pin_store
+    }
+    /// Keep restricting profile selection until [`ProfileManager::unpin`] is called.
+    ///
+    /// A persisted pin is saved for this set of heads and used whenever it is connected again.
+    pub fn pin(
+        &mut self,
+        profile_name: String,
+        persist_for: Option<&VecDeque<WlHead>>,
+    ) -> Result<(), PinError> {
+        if let Some(heads) = persist_for {
+            self.pin_store.insert(heads, profile_name.clone())?;
+        }
+        self.pin = Some(SearchPattern::Fulltext(profile_name).into());
+        Ok(())
+    }
+    /// Remove the pin and the persisted pin for these heads. Returns false if nothing was pinned.
+    pub fn unpin(&mut self, heads: &VecDeque<WlHead>) -> Result<bool, PinError> {
+        let persisted = self.pin_store.remove(heads)?;
+        Ok(self.pin.take().is_some() || persisted)
+    }
+    /// Names of the profiles matching the restriction, in the order of the config.
+    pub fn matching_profiles(&self, rest: &Restriction) -> Vec<String> {
         self.profiles
             .iter()
-            .any(|p| rest.pattern.matches(&p.name).0)
+            .filter(|p| rest.matches(&p.name))
+            .map(|p| p.name.clone())
+            .collect()
     }
 
     /// Delete old variants and reports
     pub fn clear(&mut self) {
-        self.variants.clear();
+        self.variants = Default::default();
         self.reports.clear();
+        self.pending = None;
         self.clear_cached_heads();
     }
-    fn restricted_profiles(&self) -> VecDeque<Profile> {
-        if let Some(ref rest) = self.restriction {
-            return self
-                .profiles
-                .iter()
-                .filter(|p| rest.pattern.matches(&p.name).0)
-                .cloned()
-                .collect();
+    /// The pin of the session, or else the persisted pin for these heads.
+    fn current_pin(&self, wl_heads: &VecDeque<WlHead>) -> Option<Restriction> {
+        if self.pin.is_some() {
+            return self.pin.clone();
+        }
+        let profile_name = self.pin_store.get(wl_heads)?;
+        debug!("Using the profile {profile_name:?} pinned for these heads");
+        Some(SearchPattern::Fulltext(profile_name.to_string()).into())
+    }
+    /// Names of the profiles fitting the heads, ordered by the rank of their best variant.
+    ///
+    /// Restrictions and pins are ignored. Profiles configuring the heads exactly like a better
+    /// ranked profile are left out. Returns [`None`] until the reports for these heads have been
+    /// received, see [`ProfileManager::rank_profiles`].
+    pub fn ranked_profiles(&self, wl_heads: &VecDeque<WlHead>) -> Option<Vec<String>> {
+        let ranked_heads = self.ranked_heads.as_ref()?;
+        if self.is_matching() || !same_heads(ranked_heads, wl_heads) {
+            return None;
         }
-        self.profiles.clone()
+        let reports = &self.unrestricted_reports;
+        let mut names: Vec<String> = vec![];
+        // profiles whose variants are all duplicates are never added, the limit ends the walk
+        for variant in Self::rank_variants(reports).limit(self.max_variants) {
+            if !names.contains(&variant.profile.name) {
+                names.push(variant.profile.name.clone());
+            }
+            if names.len() == reports.len() {
+                break;
+            }
+        }
+        Some(names)
+    }
+    /// Start matching the heads on the worker pool so the profiles can be ranked, unless some
+    /// reports are already awaited. The variants are kept.
+    pub fn rank_profiles(&mut self, wl_heads: VecDeque<WlHead>) {
+        if self.is_matching() {
+            return;
+        }
+        let generation = self
+            .pool
+            .submit(self.fitting_profiles(&wl_heads), wl_heads.clone());
+        self.pending = Some(PendingJob {
+            generation,
+            heads: wl_heads,
+            ranking_only: true,
+            restriction: None,
+            pinned: false,
+        });
     }
+    /// Start matching the profiles against the heads on the worker pool.
+    ///
+    /// The variants are available after the reports have been passed to
+    /// [`ProfileManager::receive_reports`]. The reports of an earlier call are ignored.
     pub fn generate_variants(&mut self, wl_heads: VecDeque<WlHead>) {
         self.cached_heads.clone_from(&wl_heads);
-        let profiles: VecDeque<Profile> = self.restricted_profiles();
-        self.lift_restriction();
+        let (restriction, pinned) = match self.lift_restriction() {
+            Some(rest) => (Some(rest), false),
+            None => {
+                let pin = self.current_pin(&wl_heads);
+                let pinned = pin.is_some();
+                (pin, pinned)
+            }
+        };
+        let generation = self
+            .pool
+            .submit(self.fitting_profiles(&wl_heads), wl_heads.clone());
+        self.pending = Some(PendingJob {
+            generation,
+            heads: wl_heads,
+            ranking_only: false,
+            restriction,
+            pinned,
+        });
+    }
 
-        for profile in profiles {
-            let (len_heads, len_outputs) = (wl_heads.len(), profile.outputs.len());
-            if len_heads != len_outputs {
-                continue;
+    fn fitting_profiles(&self, wl_heads: &VecDeque<WlHead>) -> Vec<Profile> {
+        self.profiles
+            .iter()
+            .filter(|p| p.outputs.len() == wl_heads.len())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns true while the reports of [`ProfileManager::generate_variants`] or
+    /// [`ProfileManager::rank_profiles`] are awaited.
+    pub fn is_matching(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Hands the channel the reports are sent through over, e.g. to insert it into an event loop.
+    pub fn take_report_channel(&mut self) -> Option<calloop::channel::Channel<MatchResult>> {
+        self.pool.take_channel()
+    }
+
+    /// Ranks the variants of the awaited reports. Returns false for the reports of an older job.
+    pub fn receive_reports(&mut self, result: MatchResult) -> bool {
+        let job = match self.pending.take() {
+            Some(job) if job.generation == result.generation => job,
+            pending => {
+                self.pending = pending;
+                trace!(
+                    "ignoring the reports of the outdated job {}",
+                    result.generation
+                );
+                return false;
             }
-            debug!(
-                "len(heads,outputs)=({},{}) profile.name={:?}",
-                len_heads, len_outputs, profile.name
-            );
-            if let Some(report) = ProfileMatcher::create_report(profile, wl_heads.clone()) {
-                let (len, pname) = (report.valid_variants.len(), &report.profile.name);
-                info!("len(valid variants)={} profile.name={:?}", len, pname);
-                self.reports.push_back(report);
+        };
+        let restricted = |rest: &Restriction| -> Vec<MatchReport> {
+            result
+                .reports
+                .iter()
+                .filter(|r| rest.matches(&r.profile.name))
+                .cloned()
+                .collect()
+        };
+        self.unrestricted_reports = result.reports.iter().cloned().collect();
+        self.ranked_heads = Some(job.heads);
+        if job.ranking_only {
+            return true;
+        }
+        let reports = match &job.restriction {
+            None => result.reports,
+            Some(pin) if job.pinned => {
+                let reports = restricted(pin);
+                match reports.is_empty() {
+                    true => {
+                        info!("The pinned profile does not fit the heads, ignoring the pin");
+                        result.reports
+                    }
+                    false => reports,
+                }
             }
+            Some(rest) => restricted(rest),
+        };
+        for report in reports {
+            let (len, pname) = (report.variant_count(), &report.profile.name);
+            info!("len(valid variants)={} profile.name={:?}", len, pname);
+            self.reports.push_back(report);
         }
-        self.variants = Self::collect_variants_from_reports(&self.reports);
+        self.variants = Self::rank_variants(&self.reports).limit(self.max_variants);
+        true
     }
 
-    pub fn collect_variants_from_reports(
-        reports: &VecDeque<MatchReport>,
-    ) -> VecDeque<ValidVariant> {
-        let mut variants: VecDeque<_> = reports
-            .iter()
-            .flat_map(|r| r.valid_variants.clone())
-            .collect();
-        variants.make_contiguous().sort_by(|a, b| {
-            // sort specificity decreasingly and deviation increasingly
-            (b.specificity(), a.mode_deviation()).cmp(&(a.specificity(), b.mode_deviation()))
-        });
+    /// Blocks until the awaited reports are received. Returns false if none are awaited or the
+    /// channel has been taken.
+    pub fn wait_for_reports(&mut self) -> bool {
+        while self.is_matching() {
+            let Some(result) = self.pool.recv() else {
+                return false;
+            };
+            if self.receive_reports(result) {
+                return true;
+            }
+        }
+        false
+    }
 
-        trace!("printing specificity and deviation of sorted valid variants");
-        variants.iter().for_each(|v| {
-            trace!(
-                "{}:(specificity, deviation):({}, {})",
-                v.idx_str(),
-                v.specificity(),
-                v.mode_deviation()
-            )
-        });
-        let (n, l) = (reports.len(), variants.len());
+    /// Rank the variants of all reports. Specificity is sorted decreasingly and deviation
+    /// increasingly. The variants are built lazily while iterating.
+    pub fn rank_variants(reports: &VecDeque<MatchReport>) -> RankedVariants {
+        let variants = RankedVariants::new(reports);
+        let (n, l) = (reports.len(), variants.total());
         debug!("len(total valid variants over {n} reports)={l}",);
         variants
     }
 
     pub fn is_cache_outdated(&self, wl_heads: &VecDeque<WlHead>) -> bool {
-        let le_head_a: HashSet<LessEqWlHead> = self.cached_heads.iter().map(LessEqWlHead).collect();
-        let le_head_b: HashSet<LessEqWlHead> = wl_heads.iter().map(LessEqWlHead).collect();
-        le_head_a != le_head_b
+        !same_heads(&self.cached_heads, wl_heads)
     }
 
     pub fn clear_cached_heads(&mut self) {
@@ -134,13 +327,169 @@ impl ProfileManager {
     }
 }
 
+fn same_heads(a: &VecDeque<WlHead>, b: &VecDeque<WlHead>) -> bool {
+    let le_head_a: HashSet<LessEqWlHead> = a.iter().map(LessEqWlHead).collect();
+    let le_head_b: HashSet<LessEqWlHead> = b.iter().map(LessEqWlHead).collect();
+    le_head_a == le_head_b
+}
+
+impl Restriction {
+    /// Parses a search for profile names, e.g. `%desk`. Attributes of the search are ignored.
+    pub fn parse(search: &str) -> Result<Self, ParseSingleSearchError> {
+        let search: SingleSearch = search.parse()?;
+        Ok(Self {
+            pattern: search.pattern,
+            negated: search.negated,
+        })
+    }
+    pub fn matches(&self, profile_name: &str) -> bool {
+        self.pattern.matches(profile_name).0 != self.negated
+    }
+}
+
 impl From<SearchPattern> for Restriction {
     fn from(pattern: SearchPattern) -> Self {
-        Self { pattern }
+        Self {
+            pattern,
+            negated: false,
+        }
     }
 }
 impl From<regex::Regex> for Restriction {
     fn from(r: regex::Regex) -> Self {
-        Self { pattern: r.into() }
+        SearchPattern::from(r).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::settings::SettingsToml;
+    use crate::wl_backend::test_heads::dp_heads;
+
+    use rstest::rstest;
+
+    use crate::search::SearchPattern;
+
+    use super::{ProfileManager, Restriction};
+
+    #[test]
+    fn reports_keep_profile_order() {
+        let heads = dp_heads(2);
+        let config: String = (0..40)
+            .map(|idx| {
+                let outputs = match idx % 5 {
+                    // cannot match, there are only two heads
+                    0 => r#"{ search = "/DP", enable = true }"#,
+                    _ => r#"{ search = "n=DP-0", enable = true }, { search = "n=DP-1", enable = true }"#,
+                };
+                format!("[[profile]]\nname = \"{idx}\"\noutput = [{outputs}]\n")
+            })
+            .collect();
+        let config: SettingsToml = toml::from_str(&config).unwrap();
+
+        let mut pm = ProfileManager::new(config.profiles);
+        pm.generate_variants(heads);
+        assert!(pm.wait_for_reports());
+        let names: Vec<_> = pm
+            .reports()
+            .iter()
+            .map(|r| r.profile.name.clone())
+            .collect();
+        let expected: Vec<_> = (0..40)
+            .filter(|idx| idx % 5 != 0)
+            .map(|idx| idx.to_string())
+            .collect();
+        assert_eq!(names, expected);
+        assert_eq!(pm.next_variant().unwrap().profile.name, "1");
+    }
+
+    #[test]
+    fn pin_survives_head_changes() {
+        let config: SettingsToml = toml::from_str(
+            r#"
+            [[profile]]
+            name = "best"
+            output = [{ search = "n=DP-0", enable = true }]
+            [[profile]]
+            name = "pinned"
+            output = [{ search = "/DP", enable = true }]
+            [[profile]]
+            name = "two"
+            output = [{ search = "n=DP-0", enable = true }, { search = "n=DP-1", enable = true }]
+            "#,
+        )
+        .unwrap();
+        let mut pm = ProfileManager::new(config.profiles);
+        let next_profile = |pm: &mut ProfileManager, count: usize| {
+            pm.clear();
+            pm.generate_variants(dp_heads(count));
+            pm.wait_for_reports();
+            pm.next_variant().unwrap().profile.name.clone()
+        };
+        pm.pin("pinned".to_string(), None).unwrap();
+        assert_eq!(next_profile(&mut pm, 1), "pinned");
+        // the pinned profile does not fit
+        assert_eq!(next_profile(&mut pm, 2), "two");
+        assert_eq!(next_profile(&mut pm, 1), "pinned");
+        assert!(pm.unpin(&dp_heads(1)).unwrap());
+        assert!(!pm.unpin(&dp_heads(1)).unwrap());
+        assert_eq!(next_profile(&mut pm, 1), "best");
+    }
+
+    #[rstest]
+    #[case("desk", vec![])]
+    #[case("desk (dual 4k)", vec!["desk (dual 4k)"])]
+    #[case("%desk", vec!["desk (dual 4k)", "desk (single)"])]
+    #[case("/desk.*4k", vec!["desk (dual 4k)"])]
+    #[case("~*DESK*", vec!["desk (dual 4k)", "desk (single)"])]
+    #[case("!%desk", vec!["mobile"])]
+    fn restrict_by_search(#[case] search: &str, #[case] expected: Vec<&str>) {
+        let config: String = ["desk (dual 4k)", "mobile", "desk (single)"]
+            .iter()
+            .map(|name| format!("[[profile]]\nname = {name:?}\noutput = []\n"))
+            .collect();
+        let config: SettingsToml = toml::from_str(&config).unwrap();
+        let pm = ProfileManager::new(config.profiles);
+        let restriction = Restriction::parse(search).unwrap();
+        assert_eq!(pm.matching_profiles(&restriction), expected);
+        assert!(Restriction::parse("/desk(").is_err());
+    }
+
+    #[test]
+    fn ranked_profiles_ignore_restrictions() {
+        let heads = dp_heads(2);
+        let config: SettingsToml = toml::from_str(
+            r#"
+            [[profile]]
+            name = "generic"
+            output = [{ search = "/DP-0", enable = true }, { search = "/DP-1", enable = false }]
+            [[profile]]
+            name = "single"
+            output = [{ search = "n=DP-0", enable = true }]
+            [[profile]]
+            name = "exact"
+            output = [{ search = "n=DP-0", enable = true }, { search = "n=DP-1", enable = true }]
+            "#,
+        )
+        .unwrap();
+        let mut pm = ProfileManager::new(config.profiles);
+        pm.restrict(SearchPattern::Fulltext("generic".to_string()).into());
+        pm.generate_variants(heads.clone());
+        pm.wait_for_reports();
+        assert_eq!(pm.reports().len(), 1);
+        assert_eq!(
+            pm.ranked_profiles(&heads).unwrap(),
+            vec!["exact", "generic"]
+        );
+        pm.set_max_variants(Some(1));
+        assert_eq!(pm.ranked_profiles(&heads).unwrap(), vec!["exact"]);
+        // other heads are matched on the pool, the variants are kept
+        assert_eq!(pm.ranked_profiles(&dp_heads(1)), None);
+        pm.rank_profiles(dp_heads(1));
+        assert_eq!(pm.ranked_profiles(&dp_heads(1)), None);
+        assert!(pm.wait_for_reports());
+        assert_eq!(pm.ranked_profiles(&dp_heads(1)).unwrap(), vec!["single"]);
+        assert_eq!(pm.ranked_profiles(&heads), None);
+        assert_eq!(pm.reports().len(), 1);
     }
 }
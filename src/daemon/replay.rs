@@ -0,0 +1,74 @@
+use std::path::Path;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+
+use crate::ipc::IpcRequest;
+use crate::settings::Settings;
+use crate::wl_backend::{Divergence, RecordEntry, ReplayBackend};
+
+use super::ipc;
+use super::pins::PinStore;
+use super::state_machine::DaemonStateMachine;
+
+/// Replays a recording made with `--record` and prints if the daemon made the same requests.
+///
+/// Returns false if it diverged from the recording.
+pub fn replay(path: &Path) -> Result<bool, Box<dyn snafu::Error>> {
+    let (settings, pins, backend) = ReplayBackend::from_file(path)?;
+    let mut dsm = DaemonStateMachine::new(backend, replay_settings(settings));
+    dsm.pm.set_pin_store(PinStore::from_pins(pins));
+    let divergence = run(&mut dsm);
+    let replayed = dsm.backend.replayed();
+    match divergence {
+        None => {
+            println!("replayed {replayed} records, the daemon made the same requests");
+            Ok(true)
+        }
+        Some(divergence) => {
+            println!("the daemon diverged from the recording at {divergence}");
+            Ok(false)
+        }
+    }
+}
+
+/// Feeds the recorded inputs into the state machine until it diverges or the recording ends.
+pub(crate) fn run(dsm: &mut DaemonStateMachine<ReplayBackend>) -> Option<Divergence> {
+    while let Some(input) = dsm.backend.next_input() {
+        trace!("replaying {input}");
+        match input {
+            RecordEntry::Events(events) => {
+                let _ = dsm.process_event_queue(events);
+            }
+            RecordEntry::Matched => dsm.wait_for_reports(),
+            // the recorded settings are used instead of reading the config again
+            RecordEntry::Request(IpcRequest::ReloadConfig(_)) => {}
+            RecordEntry::Reload(settings) => dsm.reload_settings(replay_settings(settings)),
+            RecordEntry::ConfirmTimeout(id) => dsm.check_confirmation(id),
+            // a deferred request is recorded again when it is answered
+            RecordEntry::Request(request) => {
+                ipc::delegate_command(request, dsm);
+            }
+            _ => {}
+        }
+        if dsm.has_shutdown() || dsm.backend.divergence().is_some() {
+            break;
+        }
+    }
+    dsm.backend.finish();
+    dsm.backend.divergence()
+}
+
+// Commands are not executed again, they do not influence the requests anyway.
+fn replay_settings(mut settings: Settings) -> Settings {
+    settings
+        .profiles
+        .iter_mut()
+        .enumerate()
+        .for_each(|(idx, profile)| {
+            profile.index = idx;
+            profile.commands = None;
+            profile.outputs.iter_mut().for_each(|o| o.commands = None);
+        });
+    settings
+}
@@ -1,17 +1,25 @@
 use std::collections::VecDeque;
 use std::fmt::Display;
+use std::time::Duration;
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
+use snafu::{prelude::*, Location};
 
 use crate::error;
 use crate::execute::CommandBuilder;
 use crate::settings::Settings;
 use crate::variant::{VSMInput, ValidVariant, VariantAction, VariantState};
-use crate::wl_backend::{WlBackend, WlBackendEvent};
+use crate::wl_backend::{
+    HeadSnapshot, RecordEntry, WlBackend, WlBackendEvent, WlConfigurationError,
+};
 
+use super::match_pool::MatchResult;
 use super::profile_manager::ProfileManager;
 
+/// Number of head snapshots kept for reverting.
+const SNAPSHOT_HISTORY_LEN: usize = 8;
+
 pub struct DaemonStateMachine<B: WlBackend> {
     state: DSMState,
     skip_tests: bool,
@@ -20,14 +28,35 @@ pub struct DaemonStateMachine<B: WlBackend> {
     pub(crate) backend: B,
     // true if the state machine encountered a shutdown
     encountered_shutdown: bool,
+    // indices and profile names of the variants that failed since the heads changed the last time
+    failed_variants: Vec<String>,
+    // states of the heads before the last variants have been applied, the newest is last
+    snapshots: VecDeque<HeadSnapshot>,
+    // id of the confirmation whose timer reverts the applied variant unless it is confirmed
+    confirmation: Option<u64>,
+    // number of confirmations awaited so far, used as the id of the next one
+    confirmations: u64,
+    // confirmation id and timeout the event loop has to set up a timer for
+    confirm_timer: Option<(u64, Duration)>,
+    // changes of the heads are not acted upon while paused
+    paused: bool,
+    // variants are applied while paused until the requested switch ends
+    forced_switch: bool,
 }
 
 #[derive(Clone, Debug, Default)]
 pub enum DSMState {
     #[default]
     NoVariantApplied,
+    /// The profiles are matched against the changed heads on the worker pool.
+    Matching,
     VariantInProgress(ValidVariant),
     VariantApplied(ValidVariant),
+    /// The variant is applied but gets reverted unless it is confirmed in time.
+    AwaitingConfirmation(ValidVariant),
+    /// The heads are restored to the state before the variant has been applied.
+    /// Holds the variant being reverted, if one was applied.
+    Reverting(Option<ValidVariant>),
     RestartAfterResponse,
 }
 
@@ -35,13 +64,22 @@ impl<B: WlBackend> DaemonStateMachine<B> {
     pub fn new(backend: B, settings: Settings) -> Self {
         let state = Default::default();
         info!("Initial daemon state: {}", state);
+        let mut pm = ProfileManager::new(settings.profiles.clone());
+        pm.set_max_variants(settings.max_variants);
         Self {
             state,
             skip_tests: settings.skip_tests,
-            pm: ProfileManager::new(settings.profiles.clone()),
+            pm,
             settings,
             backend,
             encountered_shutdown: false,
+            failed_variants: Default::default(),
+            snapshots: Default::default(),
+            confirmation: None,
+            confirmations: 0,
+            confirm_timer: None,
+            paused: false,
+            forced_switch: false,
         }
     }
 
@@ -59,6 +97,9 @@ impl<B: WlBackend> DaemonStateMachine<B> {
             return self.has_shutdown();
         }
 
+        if !eq.is_empty() {
+            self.backend.record_input(RecordEntry::Events(eq.clone()));
+        }
         for event in eq {
             self.advance(event);
             if self.has_shutdown() {
@@ -83,10 +124,28 @@ impl<B: WlBackend> DaemonStateMachine<B> {
             (NoVariantApplied, AtomicChangeDone) => self.restart(),
             (NoVariantApplied, NeededResourceFinished) => self.shutdown(),
             (NoVariantApplied, Succeeded | Failed | Cancelled) => self.warn_invalid(event),
+            // replaces the job with one for the newer heads
+            (Matching, AtomicChangeDone) => self.restart(),
+            (Matching, NeededResourceFinished) => self.shutdown(),
+            (Matching, Succeeded | Failed | Cancelled) => self.warn_invalid(event),
             (VariantInProgress(v), event) => self.advance_variant(v, event),
             (VariantApplied(_), AtomicChangeDone) => self.restart(),
             (VariantApplied(_), NeededResourceFinished) => self.shutdown(),
             (VariantApplied(_), Succeeded | Failed | Cancelled) => self.warn_invalid(event),
+            (AwaitingConfirmation(_), AtomicChangeDone) => self.restart(),
+            (AwaitingConfirmation(_), NeededResourceFinished) => self.shutdown(),
+            (AwaitingConfirmation(_), Succeeded | Failed | Cancelled) => self.warn_invalid(event),
+            // wait for the response to the revert
+            (Reverting(v), AtomicChangeDone) => Reverting(v),
+            (Reverting(_), NeededResourceFinished) => self.shutdown(),
+            (Reverting(_), Succeeded) => {
+                info!("Restored the previous state of the heads");
+                self.finish_revert()
+            }
+            (Reverting(_), Failed | Cancelled) => {
+                warn!("Cannot restore the previous state of the heads");
+                self.finish_revert()
+            }
             (RestartAfterResponse, AtomicChangeDone) => RestartAfterResponse,
             (RestartAfterResponse, NeededResourceFinished) => self.shutdown(),
             (RestartAfterResponse, Succeeded | Failed | Cancelled) => self.restart(),
@@ -122,15 +181,54 @@ impl<B: WlBackend> DaemonStateMachine<B> {
                 }
                 DSMState::VariantInProgress(variant)
             }
+            // the variant has been tested before pausing
+            VariantAction::ApplyVariant if self.paused && !self.forced_switch => {
+                info!(
+                    "Paused, not applying variant {}:{:?}",
+                    variant.idx_str(),
+                    variant.profile.name
+                );
+                self.refresh_variants()
+            }
+            VariantAction::ApplyVariant if self.settings.dry_run => {
+                info!(
+                    "Dry run, not applying variant {}:{:?}",
+                    variant.idx_str(),
+                    variant.profile.name
+                );
+                let mut variant = variant;
+                let action = variant.state.advance(VSMInput::Succeeded);
+                self.do_action(action, variant)
+            }
             VariantAction::ApplyVariant => {
+                self.take_snapshot();
                 if let Err(err) = self.backend.apply(&variant) {
                     warn!("{}", error::report(&err));
                 }
                 DSMState::VariantInProgress(variant)
             }
-            VariantAction::TryNextVariant => self.next_variant(),
+            VariantAction::TryNextVariant => {
+                let label = format!("{}:{:?}", variant.idx_str(), variant.profile.name);
+                self.failed_variants.push(label);
+                let attempts = self.failed_variants.len();
+                if self
+                    .settings
+                    .max_attempts
+                    .is_some_and(|max| attempts >= max)
+                {
+                    warn!("Giving up after {attempts} failed attempts");
+                    return self.give_up();
+                }
+                self.next_variant()
+            }
             VariantAction::ExecCmd => {
-                self.execute_variant_commands(&variant);
+                self.forced_switch = false;
+                if !self.settings.dry_run {
+                    self.execute_variant_commands(&variant);
+                    if let Some(timeout) = self.confirm_timeout(&variant) {
+                        return self.await_confirmation(variant, timeout);
+                    }
+                }
                 if self.settings.oneshot {
                     // No return here because the variant is applied.
                     self.shutdown();
@@ -142,11 +240,12 @@ impl<B: WlBackend> DaemonStateMachine<B> {
     }
 
     pub fn next_variant(&mut self) -> DSMState {
+        if self.paused && !self.forced_switch {
+            debug!("Paused, not trying another variant");
+            return DSMState::NoVariantApplied;
+        }
         match self.pm.next_variant() {
-            None => match self.settings.oneshot {
-                true => self.shutdown(),
-                false => DSMState::NoVariantApplied,
-            },
+            None => self.give_up(),
             Some(mut variant) => {
                 let action = variant.start(self.skip_tests);
                 self.do_action(action, variant)
@@ -154,12 +253,28 @@ impl<B: WlBackend> DaemonStateMachine<B> {
         }
     }
 
+    /// Stops trying variants until the heads change again.
+    fn give_up(&mut self) -> DSMState {
+        self.forced_switch = false;
+        if !self.failed_variants.is_empty() {
+            let tried = self.failed_variants.join(", ");
+            warn!("No variant could be applied, tried: {tried}");
+        }
+        match self.settings.oneshot {
+            true => self.shutdown(),
+            false => DSMState::NoVariantApplied,
+        }
+    }
+
     pub fn restart(&mut self) -> DSMState {
         if let DSMState::VariantInProgress(v) = &self.state {
             if let VariantState::Testing | VariantState::Applying = v.state {
                 return DSMState::RestartAfterResponse;
             }
         }
+        if self.paused && !self.forced_switch {
+            return self.refresh_variants();
+        }
 
         if let Some(heads) = self.backend.export_heads() {
             // If there is no relevant change, don't restart.
@@ -169,11 +284,232 @@ impl<B: WlBackend> DaemonStateMachine<B> {
             }
             // Else regenerate variants.
             self.pm.clear();
+            self.failed_variants.clear();
+            self.confirmation = None;
             self.pm.generate_variants(heads);
+            return DSMState::Matching;
         }
         self.next_variant()
     }
 
+    /// Continues with the variants generated from the reports of the worker pool.
+    pub fn receive_reports(&mut self, result: MatchResult) {
+        if self.pm.receive_reports(result) {
+            self.reports_received();
+        }
+    }
+
+    /// Blocks until the reports of the worker pool are received, e.g. while replaying.
+    pub fn wait_for_reports(&mut self) {
+        if self.pm.wait_for_reports() {
+            self.reports_received();
+        }
+    }
+
+    fn reports_received(&mut self) {
+        self.backend.record_input(RecordEntry::Matched);
+        if let DSMState::Matching = self.state {
+            self.state = self.next_variant();
+            info!("New daemon state: {}", self.state);
+        }
+    }
+
+    /// Stops acting on changes of the heads. Returns false if it is already paused.
+    pub fn pause(&mut self) -> bool {
+        if self.paused {
+            return false;
+        }
+        info!("Pausing, changes of the heads are ignored until resuming");
+        self.paused = true;
+        true
+    }
+
+    /// Acts on changes of the heads again, starting with the current heads. Returns false if it
+    /// is not paused.
+    pub fn resume(&mut self) -> bool {
+        if !self.paused {
+            return false;
+        }
+        info!("Resuming");
+        self.paused = false;
+        self.simulate_change();
+        true
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Keeps the variants up to date with the heads without applying any of them.
+    fn refresh_variants(&mut self) -> DSMState {
+        let heads = self.backend.export_heads();
+        if let Some(heads) = heads.filter(|heads| self.pm.is_cache_outdated(heads)) {
+            debug!("Paused, not applying a variant for the changed heads");
+            self.pm.clear();
+            self.failed_variants.clear();
+            self.pm.generate_variants(heads);
+        }
+        match &self.state {
+            DSMState::VariantApplied(_) | DSMState::AwaitingConfirmation(_) => self.state.clone(),
+            _ => DSMState::NoVariantApplied,
+        }
+    }
+
+    /// Returns the confirmation timeout of the profile, falling back to the global one.
+    fn confirm_timeout(&self, variant: &ValidVariant) -> Option<Duration> {
+        let profile_timeout = variant.profile.confirm_timeout.map(Duration::from_millis);
+        profile_timeout.or(self.settings.confirm_timeout)
+    }
+
+    fn await_confirmation(&mut self, variant: ValidVariant, timeout: Duration) -> DSMState {
+        info!(
+            "Reverting variant {}:{:?} in {timeout:?} unless it is confirmed",
+            variant.idx_str(),
+            variant.profile.name
+        );
+        self.confirmations += 1;
+        self.confirmation = Some(self.confirmations);
+        self.confirm_timer = Some((self.confirmations, timeout));
+        DSMState::AwaitingConfirmation(variant)
+    }
+
+    /// Returns the id and the timeout after which [`Self::check_confirmation`] has to be called
+    /// with that id.
+    pub fn take_confirm_timer(&mut self) -> Option<(u64, Duration)> {
+        self.confirm_timer.take()
+    }
+
+    /// Reverts the applied variant if the confirmation with this id is still awaited.
+    pub fn check_confirmation(&mut self, id: u64) {
+        let DSMState::AwaitingConfirmation(variant) = self.state.clone() else {
+            return;
+        };
+        // the timer of an older confirmation
+        if self.confirmation != Some(id) {
+            return;
+        }
+        self.backend.record_input(RecordEntry::ConfirmTimeout(id));
+        warn!(
+            "Variant {}:{:?} has not been confirmed in time",
+            variant.idx_str(),
+            variant.profile.name
+        );
+        self.state = match self.revert(Some(variant)) {
+            Ok(state) => state,
+            Err(err) => {
+                warn!("{}", error::report(&err));
+                self.finish_revert()
+            }
+        };
+        info!("New daemon state: {}", self.state);
+    }
+
+    /// Keeps the variant awaiting confirmation. Returns false if there is none.
+    pub fn confirm(&mut self) -> bool {
+        let DSMState::AwaitingConfirmation(variant) = self.state.clone() else {
+            return false;
+        };
+        info!(
+            "Variant {}:{:?} has been confirmed",
+            variant.idx_str(),
+            variant.profile.name
+        );
+        self.confirmation = None;
+        self.state = DSMState::VariantApplied(variant);
+        info!("New daemon state: {}", self.state);
+        if self.settings.oneshot {
+            self.shutdown();
+        }
+        true
+    }
+
+    /// Applies this variant instead of the next ranked one. Returns false if the state machine
+    /// is waiting for the response to another request.
+    pub fn switch_to_variant(&mut self, mut variant: ValidVariant) -> bool {
+        if self.is_busy() {
+            return false;
+        }
+        info!(
+            "Switching to variant {}:{:?}",
+            variant.idx_str(),
+            variant.profile.name
+        );
+        self.confirmation = None;
+        self.forced_switch = true;
+        let action = variant.start(self.skip_tests);
+        self.state = self.do_action(action, variant);
+        info!("New daemon state: {}", self.state);
+        true
+    }
+
+    /// Returns true while the state machine waits for the response to a request.
+    pub fn is_busy(&self) -> bool {
+        matches!(
+            self.state,
+            DSMState::Matching
+                | DSMState::VariantInProgress(_)
+                | DSMState::Reverting(_)
+                | DSMState::RestartAfterResponse
+        )
+    }
+
+    /// Restores the state of the heads before the last variant has been applied.
+    ///
+    /// Each call goes back one more step in the history of snapshots.
+    pub fn revert_to_previous(&mut self) -> Result<(), RevertError> {
+        let variant = match self.state.clone() {
+            DSMState::NoVariantApplied => None,
+            DSMState::VariantApplied(v) | DSMState::AwaitingConfirmation(v) => Some(v),
+            state => {
+                let state = state.to_string();
+                return BusyCtx { state }.fail();
+            }
+        };
+        self.state = self.revert(variant)?;
+        info!("New daemon state: {}", self.state);
+        Ok(())
+    }
+
+    fn revert(&mut self, variant: Option<ValidVariant>) -> Result<DSMState, RevertError> {
+        self.confirmation = None;
+        ensure!(!self.snapshots.is_empty(), NoSnapshotCtx);
+        let heads = self.backend.export_heads().unwrap_or_default();
+        // snapshots of other heads are kept until those heads are connected again
+        let Some(idx) = self.snapshots.iter().rposition(|s| s.fits(&heads)) else {
+            let missing = self.snapshots.back().map(|s| s.missing_heads(&heads));
+            let heads = missing.unwrap_or_default().join(", ");
+            ensure!(heads.is_empty(), DisconnectedHeadsCtx { heads });
+            return NoSnapshotForHeadsCtx.fail();
+        };
+        let snapshot = self.snapshots.remove(idx).context(NoSnapshotCtx)?;
+        // a snapshot that cannot be applied to its own heads is of no use anymore
+        self.backend
+            .apply_snapshot(&snapshot)
+            .context(ApplySnapshotCtx)?;
+        Ok(DSMState::Reverting(variant))
+    }
+
+    /// Remembers the current state of the heads before a variant is applied.
+    fn take_snapshot(&mut self) {
+        let Some(heads) = self.backend.export_heads() else {
+            warn!("Cannot take a snapshot of the heads");
+            return;
+        };
+        if self.snapshots.len() == SNAPSHOT_HISTORY_LEN {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(HeadSnapshot::new(heads));
+    }
+
+    fn finish_revert(&mut self) -> DSMState {
+        if self.settings.oneshot {
+            return self.shutdown();
+        }
+        // only restarts if the heads changed in the meantime
+        self.state = DSMState::NoVariantApplied;
+        self.restart()
+    }
+
     pub fn shutdown(&mut self) -> DSMState {
         self.encountered_shutdown = true;
         DSMState::NoVariantApplied
@@ -187,9 +523,27 @@ impl<B: WlBackend> DaemonStateMachine<B> {
         &self.state
     }
 
+    /// Uses the reloaded settings for the current heads, the pins are kept.
+    pub fn reload_settings(&mut self, settings: Settings) {
+        self.pm.set_profiles(settings.profiles.clone());
+        self.pm.set_max_variants(settings.max_variants);
+        self.settings = settings;
+        self.simulate_change();
+    }
+
     pub fn simulate_change(&mut self) {
         debug!("simulating change");
+        self.forced_switch = false;
+        self.pm.clear_cached_heads();
+        self.advance(WlBackendEvent::AtomicChangeDone);
+    }
+
+    /// Like [`Self::simulate_change`] but applies a variant even while paused, for switching
+    /// profiles explicitly. Lasts until a variant is applied or none is left.
+    pub fn simulate_switch(&mut self) {
+        debug!("simulating change for a switch");
         self.pm.clear_cached_heads();
+        self.forced_switch = true;
         self.advance(WlBackendEvent::AtomicChangeDone);
     }
 
@@ -221,13 +575,313 @@ impl Display for DSMState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DSMState::NoVariantApplied => write!(f, "NoVariantApplied"),
+            DSMState::Matching => write!(f, "Matching"),
             DSMState::VariantInProgress(v) => {
                 write!(f, "VariantInProgress {}:{:?}", v.idx_str(), v.profile.name)
             }
             DSMState::VariantApplied(v) => {
                 write!(f, "VariantApplied {}:{:?}", v.idx_str(), v.profile.name)
             }
+            DSMState::AwaitingConfirmation(v) => {
+                write!(
+                    f,
+                    "AwaitingConfirmation {}:{:?}",
+                    v.idx_str(),
+                    v.profile.name
+                )
+            }
+            DSMState::Reverting(Some(v)) => {
+                write!(f, "Reverting {}:{:?}", v.idx_str(), v.profile.name)
+            }
+            DSMState::Reverting(None) => write!(f, "Reverting"),
             DSMState::RestartAfterResponse => write!(f, "RestartAfterResponse"),
         }
     }
 }
+
+#[derive(Debug, Snafu)]
+#[snafu(context(suffix(Ctx)))]
+#[snafu(visibility(pub(crate)))]
+pub enum RevertError {
+    #[snafu(display("[{location}] Cannot revert at state {state}"))]
+    Busy { location: Location, state: String },
+    #[snafu(display("[{location}] There is no previous state of the heads to revert to"))]
+    NoSnapshot { location: Location },
+    #[snafu(display(
+        "[{location}] There is no previous state of the connected heads to revert to"
+    ))]
+    NoSnapshotForHeads { location: Location },
+    #[snafu(display(
+        "[{location}] Heads of the previous state are not connected anymore: {heads}"
+    ))]
+    DisconnectedHeads { location: Location, heads: String },
+    #[snafu(display("[{location}] Cannot apply the previous state of the heads"))]
+    ApplySnapshot {
+        source: WlConfigurationError,
+        location: Location,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use rstest::rstest;
+    use wayland_client::backend::WaylandError;
+
+    use crate::daemon::profile_manager::ProfileManager;
+    use crate::settings::{Settings, SettingsToml};
+    use crate::variant::ValidVariant;
+    use crate::wl_backend::test_heads::head;
+    use crate::wl_backend::{
+        HeadSnapshot, WlBackend, WlBackendEvent, WlConfigurationError, WlHead,
+    };
+
+    use super::{DSMState, DaemonStateMachine, RevertError};
+
+    /// Counts the tested and applied variants, the results are sent by the test.
+    #[derive(Default)]
+    struct CountingBackend {
+        heads: VecDeque<WlHead>,
+        tests: usize,
+        applies: usize,
+    }
+
+    impl WlBackend for CountingBackend {
+        fn apply(&mut self, _variant: &ValidVariant) -> Result<(), WlConfigurationError> {
+            self.applies += 1;
+            Ok(())
+        }
+        fn test(&mut self, _variant: &ValidVariant) -> Result<(), WlConfigurationError> {
+            self.tests += 1;
+            Ok(())
+        }
+        fn drain_event_queue(&mut self) -> VecDeque<WlBackendEvent> {
+            Default::default()
+        }
+        fn export_heads(&self) -> Option<VecDeque<WlHead>> {
+            Some(self.heads.clone())
+        }
+        fn flush(&self) -> Result<(), WaylandError> {
+            Ok(())
+        }
+    }
+
+    /// Two heads with three modes each, resulting in nine variants.
+    fn dsm(
+        max_variants: Option<usize>,
+        max_attempts: Option<usize>,
+    ) -> DaemonStateMachine<CountingBackend> {
+        let heads = (0..2)
+            .map(|id| head(id, &format!("DP-{id}"), &[60000, 59940, 50000]))
+            .collect();
+        let config: SettingsToml = toml::from_str(
+            r#"
+            [[profile]]
+            name = "two"
+            output = [
+                { search = "n=DP-0", enable = true, mode = "1920x1080" },
+                { search = "n=DP-1", enable = true, mode = "1920x1080" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let settings = Settings {
+            profiles: config.profiles,
+            skip_tests: false,
+            oneshot: false,
+            timeout: Duration::ZERO,
+            config_path: PathBuf::new(),
+            max_variants,
+            max_attempts,
+            dry_run: false,
+            confirm_timeout: None,
+        };
+        let backend = CountingBackend {
+            heads,
+            ..Default::default()
+        };
+        DaemonStateMachine::new(backend, settings)
+    }
+
+    /// Advances with a change of the heads and waits for the matching workers.
+    fn change_heads(dsm: &mut DaemonStateMachine<CountingBackend>) {
+        dsm.advance(WlBackendEvent::AtomicChangeDone);
+        assert!(matches!(dsm.state(), DSMState::Matching));
+        dsm.wait_for_reports();
+    }
+
+    #[rstest]
+    #[case(None, None, 9)]
+    #[case(Some(4), None, 4)]
+    #[case(None, Some(3), 3)]
+    #[case(Some(2), Some(3), 2)]
+    fn give_up_after_limits(
+        #[case] max_variants: Option<usize>,
+        #[case] max_attempts: Option<usize>,
+        #[case] tests: usize,
+    ) {
+        let mut dsm = dsm(max_variants, max_attempts);
+        change_heads(&mut dsm);
+        while let DSMState::VariantInProgress(_) = dsm.state() {
+            dsm.advance(WlBackendEvent::Failed);
+        }
+        assert!(matches!(dsm.state(), DSMState::NoVariantApplied));
+        assert_eq!(dsm.backend.tests, tests);
+        assert_eq!(dsm.failed_variants.len(), tests);
+    }
+
+    #[test]
+    fn dry_run_does_not_apply() {
+        let mut dsm = dsm(None, None);
+        dsm.settings.dry_run = true;
+        change_heads(&mut dsm);
+        dsm.advance(WlBackendEvent::Succeeded);
+        let DSMState::VariantApplied(variant) = dsm.state() else {
+            panic!("variant has not been applied in the dry run");
+        };
+        assert_eq!(variant.idx_str(), "0,0");
+        assert_eq!((dsm.backend.tests, dsm.backend.applies), (1, 0));
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    fn revert_unless_confirmed(#[case] confirm: bool) {
+        let mut dsm = dsm(None, None);
+        dsm.settings.confirm_timeout = Some(Duration::ZERO);
+        change_heads(&mut dsm);
+        dsm.advance(WlBackendEvent::Succeeded);
+        dsm.advance(WlBackendEvent::Succeeded);
+        assert!(matches!(dsm.state(), DSMState::AwaitingConfirmation(_)));
+        let (id, timeout) = dsm.take_confirm_timer().unwrap();
+        assert_eq!(timeout, Duration::ZERO);
+
+        if confirm {
+            assert!(dsm.confirm());
+        }
+        dsm.check_confirmation(id);
+        match confirm {
+            true => assert!(matches!(dsm.state(), DSMState::VariantApplied(_))),
+            false => {
+                assert!(matches!(dsm.state(), DSMState::Reverting(_)));
+                dsm.advance(WlBackendEvent::Succeeded);
+                assert!(matches!(dsm.state(), DSMState::NoVariantApplied));
+            }
+        }
+        // the snapshot is applied like a variant
+        assert_eq!(dsm.backend.applies, if confirm { 1 } else { 2 });
+        assert!(!dsm.confirm());
+    }
+
+    #[test]
+    fn switch_to_variant() {
+        let mut dsm = dsm(None, None);
+        change_heads(&mut dsm);
+        let variant = || {
+            let variants = ProfileManager::rank_variants(dsm.pm.reports());
+            variants.find(0, 3).unwrap()
+        };
+        let third = variant();
+        assert!(!dsm.switch_to_variant(third.clone()));
+        dsm.advance(WlBackendEvent::Succeeded);
+        dsm.advance(WlBackendEvent::Succeeded);
+        assert!(matches!(dsm.state(), DSMState::VariantApplied(v) if v.idx_str() == "0,0"));
+
+        assert!(dsm.switch_to_variant(third));
+        dsm.advance(WlBackendEvent::Succeeded);
+        dsm.advance(WlBackendEvent::Succeeded);
+        assert!(matches!(dsm.state(), DSMState::VariantApplied(v) if v.idx_str() == "0,3"));
+        assert_eq!((dsm.backend.tests, dsm.backend.applies), (2, 2));
+    }
+
+    #[test]
+    fn pause_ignores_head_changes() {
+        let mut dsm = dsm(None, None);
+        assert!(dsm.pause());
+        assert!(!dsm.pause());
+        let shutdown = dsm.process_event_queue([WlBackendEvent::AtomicChangeDone].into());
+        assert!(!shutdown);
+        assert!(matches!(dsm.state(), DSMState::NoVariantApplied));
+        assert_eq!(dsm.backend.tests, 0);
+        // the variants are generated for the changed heads nonetheless
+        dsm.wait_for_reports();
+        assert!(matches!(dsm.state(), DSMState::NoVariantApplied));
+        assert_eq!(dsm.pm.reports().len(), 1);
+
+        assert!(dsm.resume());
+        assert!(!dsm.resume());
+        dsm.wait_for_reports();
+        assert!(matches!(dsm.state(), DSMState::VariantInProgress(_)));
+        assert_eq!(dsm.backend.tests, 1);
+    }
+
+    #[rstest]
+    #[case(WlBackendEvent::Succeeded)]
+    #[case(WlBackendEvent::Cancelled)]
+    fn pause_during_variant_in_progress(#[case] response: WlBackendEvent) {
+        let mut dsm = dsm(None, None);
+        change_heads(&mut dsm);
+        assert!(dsm.pause());
+        // another display is connected to the same port while the variant is tested
+        dsm.backend.heads[1] = head(2, "DP-1", &[60000, 59940, 50000]);
+        let events = [WlBackendEvent::AtomicChangeDone, response];
+        assert!(!dsm.process_event_queue(events.into()));
+        assert!(matches!(dsm.state(), DSMState::NoVariantApplied));
+        dsm.wait_for_reports();
+        assert!(matches!(dsm.state(), DSMState::NoVariantApplied));
+        assert_eq!(dsm.pm.reports().len(), 1);
+        assert_eq!((dsm.backend.tests, dsm.backend.applies), (1, 0));
+
+        // reloading does not start a variant, an explicit switch does
+        dsm.reload_settings(dsm.settings.clone());
+        dsm.wait_for_reports();
+        assert!(matches!(dsm.state(), DSMState::NoVariantApplied));
+        dsm.simulate_switch();
+        dsm.wait_for_reports();
+        dsm.advance(WlBackendEvent::Succeeded);
+        dsm.advance(WlBackendEvent::Succeeded);
+        assert!(matches!(dsm.state(), DSMState::VariantApplied(_)));
+        assert_eq!((dsm.backend.tests, dsm.backend.applies), (2, 1));
+    }
+
+    #[test]
+    fn revert_to_previous() {
+        let mut dsm = dsm(None, None);
+        assert!(matches!(
+            dsm.revert_to_previous(),
+            Err(RevertError::NoSnapshot { .. })
+        ));
+        change_heads(&mut dsm);
+        assert!(matches!(
+            dsm.revert_to_previous(),
+            Err(RevertError::Busy { .. })
+        ));
+        dsm.advance(WlBackendEvent::Succeeded);
+        dsm.advance(WlBackendEvent::Succeeded);
+        assert!(matches!(dsm.state(), DSMState::VariantApplied(_)));
+
+        // a head of the snapshot has been disconnected
+        let head = dsm.backend.heads.pop_back().unwrap();
+        let err = dsm.revert_to_previous().unwrap_err();
+        assert!(matches!(err, RevertError::DisconnectedHeads { ref heads, .. } if heads == "DP-1"));
+        assert!(matches!(dsm.state(), DSMState::VariantApplied(_)));
+
+        // a newer snapshot of other heads is skipped and kept
+        let other = HeadSnapshot::new(dsm.backend.heads.clone());
+        dsm.snapshots.push_back(other);
+        dsm.backend.heads.push_back(head);
+        dsm.revert_to_previous().unwrap();
+        assert!(matches!(dsm.state(), DSMState::Reverting(Some(_))));
+        dsm.advance(WlBackendEvent::Succeeded);
+        assert!(matches!(dsm.state(), DSMState::NoVariantApplied));
+        assert_eq!(dsm.backend.applies, 2);
+        assert_eq!(dsm.snapshots.len(), 1);
+        assert!(matches!(
+            dsm.revert_to_previous(),
+            Err(RevertError::NoSnapshotForHeads { .. })
+        ));
+    }
+}
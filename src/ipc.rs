@@ -10,22 +10,39 @@ use log::{debug, error, info, trace, warn};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use snafu::{prelude::*, Location};
 
-use crate::matching::MatchReport;
+use crate::matching::{MatchReport, ProfileExplanation};
 use crate::wl_backend::WlHead;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub(crate) enum IpcRequest {
+pub enum IpcRequest {
+    Confirm,
     CurrentHeads,
     CurrentState,
+    Explain(Option<String>),
     MatchReports,
+    NextProfile,
+    Pause,
+    PreviousProfile,
     ReloadConfig(Option<PathBuf>),
-    SwitchProfile(String),
+    Resume,
+    Revert,
+    SwitchProfile {
+        name: String,
+        pin: bool,
+        persist: bool,
+    },
+    SwitchVariant {
+        profile: usize,
+        variant: usize,
+    },
+    Unpin,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub(crate) enum IpcResponse {
     CurrentHeads(VecDeque<WlHead>),
     Error(String),
+    Explanations(Vec<ProfileExplanation>),
     Generic(String),
     MatchReports(VecDeque<MatchReport>),
     Success,
@@ -75,6 +92,12 @@ impl IpcStream {
             .context(ShutdownCtx { direction })
     }
 
+    /// Duplicates the stream, e.g. to answer after the event source of the stream is removed.
+    pub(crate) fn try_clone(&self) -> Result<Self, IpcError> {
+        let stream = self.stream.try_clone().context(SocketCloneCtx)?;
+        Ok(Self::from(stream))
+    }
+
     pub(crate) fn into_event_source(self) -> calloop::generic::Generic<Self> {
         self.into()
     }
@@ -137,6 +160,11 @@ pub(crate) enum IpcError {
         source: std::io::Error,
         location: Location,
     },
+    #[snafu(display("[{location}] Cannot duplicate socket"))]
+    SocketClone {
+        source: std::io::Error,
+        location: Location,
+    },
     #[snafu(display("[{location}] Cannot shutdown stream for {direction:?} directon(s)"))]
     Shutdown {
         source: std::io::Error,
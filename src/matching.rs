@@ -1,9 +1,12 @@
 mod comparator;
+mod explain;
 mod hopcroft_karp_map;
 mod pairing;
 mod pipelined;
+mod ranked;
 
 use std::collections::VecDeque;
+use std::sync::Arc;
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
@@ -12,16 +15,19 @@ use serde::{Deserialize, Serialize};
 use crate::error;
 use crate::pipeline::Pipeline;
 use crate::profile::{Output, Profile};
-use crate::variant::ValidVariant;
 use crate::wl_backend::WlHead;
 
 pub use self::comparator::{Comparator, ComparatorInfo};
+pub use self::explain::{
+    HeadComparison, OutputExplanation, ProfileExplanation, SearchFailure, Verdict,
+};
 pub use self::hopcroft_karp_map::{Edge, HopcroftKarpMap};
 pub use self::pairing::{
     IntermediatePairing, IntermediatePairingWithMultipleModes, IntermediatePairingWithoutMode,
-    Pairing, PairingWithMode, PairingWithoutMode, UnrelatedPairing,
+    Pairing, PairingConfig, PairingWithMode, PairingWithoutMode, UnrelatedPairing,
 };
 use self::pipelined::{MatchPipelineError, MatcherOutput};
+pub use self::ranked::{RankedVariants, SubsetVariants};
 
 pub const MAX_RR_DEVIATION: i32 = 500;
 
@@ -30,7 +36,7 @@ pub struct ProfileMatcher;
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MatchReport {
     // input
-    pub(crate) profile: Profile,
+    pub(crate) profile: Arc<Profile>,
     wl_heads: VecDeque<WlHead>,
 
     // from stage 1
@@ -38,10 +44,21 @@ pub struct MatchReport {
     pub(crate) unpaired_outputs: Vec<Output>,
     pub(crate) unrelated_pairings: Vec<UnrelatedPairing>,
     // from stage 2
-    // contains lists of pairings where [pairing].len() != wl_heads.len()
+    // contains a maximum matching where [pairing].len() != wl_heads.len(),
+    // if there is no valid subset
     pub(crate) invalid_subsets: Vec<Vec<IntermediatePairing>>,
     // from stage 3
-    pub(crate) valid_variants: VecDeque<ValidVariant>,
+    pub(crate) valid_subsets: Vec<SubsetVariants>,
+}
+
+impl MatchReport {
+    /// Returns the number of valid variants without building them.
+    pub fn variant_count(&self) -> usize {
+        self.valid_subsets
+            .iter()
+            .map(|s| s.len())
+            .fold(0, usize::saturating_add)
+    }
 }
 
 impl ProfileMatcher {
@@ -50,7 +67,8 @@ impl ProfileMatcher {
             .add_pipe(pipelined::Stage2)
             .add_pipe(pipelined::Stage3);
 
-        let input = pipelined::MatcherInput::new(wl_heads, profile.clone());
+        let profile = Arc::new(profile);
+        let input = pipelined::MatcherInput::new(wl_heads, Arc::clone(&profile));
         let result: Result<MatcherOutput, MatchPipelineError> = p.execute(input);
 
         match result {
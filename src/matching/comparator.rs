@@ -83,6 +83,9 @@ impl Comparator {
             .model(wl_head.model())
             .serial(wl_head.serial_number())
             .vendor(wl_head.make())
+            .edid(wl_head.edid())
+            .size(wl_head.size())
+            .modes(wl_head.modes())
             .run();
         debug!("search_result.is_ok={}", search_result.is_ok());
 
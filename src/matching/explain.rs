@@ -0,0 +1,263 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::profile::{Mode, Profile};
+use crate::search::{SearchField, SearchResult};
+use crate::wl_backend::{WlBaseMode, WlHead};
+
+use super::{Comparator, ProfileMatcher, UnrelatedPairing};
+
+/// Why a profile can or cannot be used with the current heads.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProfileExplanation {
+    pub name: String,
+    pub verdict: Verdict,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Verdict {
+    /// The profile was not considered at all.
+    Skipped { outputs: usize, heads: usize },
+    /// Every output was compared with every head.
+    Compared {
+        outputs: Vec<OutputExplanation>,
+        variants: usize,
+    },
+}
+
+/// How an output compares to each head.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutputExplanation {
+    pub search: String,
+    pub mode: Option<Mode>,
+    pub heads: Vec<HeadComparison>,
+}
+
+/// The result of comparing an output with a head. They fit if nothing failed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HeadComparison {
+    pub head: String,
+    pub failed_searches: Vec<SearchFailure>,
+    pub unsupported_mode: Option<Mode>,
+    /// Only filled if the mode is unsupported
+    pub offered_modes: Vec<WlBaseMode>,
+}
+
+/// A search of an output that did not match a head.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SearchFailure {
+    pub search: String,
+    pub negated: bool,
+    pub fields: Vec<SearchField>,
+}
+
+impl ProfileMatcher {
+    /// Compares every output of the profile with every head and collects the reasons why they
+    /// do not fit.
+    pub fn explain(profile: &Profile, wl_heads: &VecDeque<WlHead>) -> ProfileExplanation {
+        let name = profile.name.clone();
+        if profile.outputs.len() != wl_heads.len() {
+            let (outputs, heads) = (profile.outputs.len(), wl_heads.len());
+            let verdict = Verdict::Skipped { outputs, heads };
+            return ProfileExplanation { name, verdict };
+        }
+
+        let outputs = profile
+            .outputs
+            .iter()
+            .map(|output| {
+                let info = Comparator::collect_intermediate_pairings(
+                    std::iter::once(output),
+                    wl_heads.iter(),
+                );
+                let heads = wl_heads
+                    .iter()
+                    .map(|head| {
+                        let upair = info.unrelated_pairings.iter().find(|u| u.wl_head == *head);
+                        HeadComparison::new(head, upair)
+                    })
+                    .collect();
+                OutputExplanation {
+                    search: output.search_pattern.to_string(),
+                    mode: output.mode,
+                    heads,
+                }
+            })
+            .collect();
+        let variants =
+            Self::create_report(profile.clone(), wl_heads.clone()).map_or(0, |r| r.variant_count());
+        let verdict = Verdict::Compared { outputs, variants };
+        ProfileExplanation { name, verdict }
+    }
+}
+
+impl HeadComparison {
+    fn new(head: &WlHead, upair: Option<&UnrelatedPairing>) -> Self {
+        let failed_searches = upair
+            .and_then(|u| u.failed_search.as_ref())
+            .map(SearchFailure::collect)
+            .unwrap_or_default();
+        let unsupported_mode = upair.and_then(|u| u.unsupported_mode);
+        let offered_modes = match unsupported_mode {
+            Some(_) => head.modes().iter().map(|m| m.wl_base_mode()).collect(),
+            None => vec![],
+        };
+        Self {
+            head: head.name().to_string(),
+            failed_searches,
+            unsupported_mode,
+            offered_modes,
+        }
+    }
+
+    pub fn fits(&self) -> bool {
+        self.failed_searches.is_empty() && self.unsupported_mode.is_none()
+    }
+}
+
+impl SearchFailure {
+    fn collect(result: &SearchResult) -> Vec<Self> {
+        result
+            .failed_searches()
+            .into_iter()
+            .map(|ssr| Self {
+                search: ssr.search().to_string(),
+                negated: ssr.search().negated,
+                fields: ssr.failed_fields(),
+            })
+            .collect()
+    }
+}
+
+impl Display for ProfileExplanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.verdict {
+            Verdict::Skipped { outputs, heads } => {
+                write!(f, "profile {:?}: skipped, it has {outputs} ", self.name)?;
+                write!(f, "output(s) but {heads} display(s) are connected")
+            }
+            Verdict::Compared { outputs, variants } => {
+                write!(f, "profile {:?}: {variants} valid variant(s)", self.name)?;
+                for (idx, output) in outputs.iter().enumerate() {
+                    write!(f, "\n  output {idx} (search {:?}", output.search)?;
+                    if let Some(mode) = output.mode {
+                        write!(f, ", mode {mode}")?;
+                    }
+                    write!(f, ")")?;
+                    for head in output.heads.iter() {
+                        write!(f, "\n    {}", head)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for HeadComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: ", self.head)?;
+        if self.fits() {
+            return write!(f, "fits");
+        }
+        let mut reasons: Vec<_> = self.failed_searches.iter().map(|s| s.to_string()).collect();
+        if let Some(mode) = self.unsupported_mode {
+            let offered: Vec<_> = self.offered_modes.iter().map(|m| m.to_string()).collect();
+            reasons.push(format!(
+                "mode {mode} is not supported, offered modes: {}",
+                offered.join(", ")
+            ));
+        }
+        write!(f, "{}", reasons.join("; "))
+    }
+}
+
+impl Display for SearchFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields: Vec<&str> = self.fields.iter().map(|f| f.as_str()).collect();
+        match self.negated {
+            true => write!(f, "excluded by {:?} ({})", self.search, fields.join(", ")),
+            false => write!(
+                f,
+                "search {:?} failed on {}",
+                self.search,
+                fields.join(", ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::settings::SettingsToml;
+    use crate::wl_backend::test_heads::head_with_base;
+    use crate::wl_backend::{WlBaseHead, WlHead};
+
+    use super::{ProfileExplanation, ProfileMatcher, Verdict};
+
+    fn heads() -> VecDeque<WlHead> {
+        ["DP-1", "eDP-1"]
+            .into_iter()
+            .enumerate()
+            .map(|(id, name)| {
+                let base = WlBaseHead {
+                    name: name.to_string(),
+                    make: "Dell".to_string(),
+                    ..Default::default()
+                };
+                head_with_base(id, base, &[60000])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn explain_mismatches() {
+        let config: SettingsToml = toml::from_str(
+            r#"
+            [[profile]]
+            name = "desk"
+            output = [
+                { search = ["v=Dell", "n=DP-1"], enable = true, mode = "3840x2160" },
+                { search = "!n=DP-1", enable = true },
+            ]
+            [[profile]]
+            name = "single"
+            output = [{ search = "n=DP-1", enable = true }]
+            "#,
+        )
+        .unwrap();
+        let heads = heads();
+
+        let desk = ProfileMatcher::explain(&config.profiles[0], &heads);
+        // explanations are sent to shikanectl
+        let ron = ron::ser::to_string(&desk).unwrap();
+        let desk: ProfileExplanation = ron::de::from_str(&ron).unwrap();
+        let Verdict::Compared { outputs, variants } = desk.verdict else {
+            panic!("profile has not been compared");
+        };
+        assert_eq!(variants, 0);
+        let dp1 = &outputs[0].heads[0];
+        assert!(dp1.failed_searches.is_empty());
+        assert_eq!(dp1.offered_modes.len(), 1);
+        let edp1 = &outputs[0].heads[1];
+        assert_eq!(edp1.failed_searches.len(), 1);
+        assert_eq!(edp1.failed_searches[0].search, "n=DP-1");
+        assert!(edp1.unsupported_mode.is_some());
+        assert!(outputs[1].heads[0].failed_searches[0].negated);
+        assert!(outputs[1].heads[1].fits());
+        assert!(edp1.to_string().contains("failed on Name"));
+
+        let single = ProfileMatcher::explain(&config.profiles[1], &heads);
+        assert!(matches!(
+            single.verdict,
+            Verdict::Skipped {
+                outputs: 1,
+                heads: 2
+            }
+        ));
+    }
+}
@@ -43,6 +43,145 @@ impl HopcroftKarpMap {
             .into_iter()
             .filter_map(move |me| map.pairs.remove(&me))
     }
+
+    /// Enumerate every perfect matching of a bipartite graph with `size` vertices on each side.
+    ///
+    /// A matching is returned as the ascending list of the indices of its edges. The matchings
+    /// are returned in lexicographic order, which is the order in which they occur among the
+    /// `size`-element combinations of `edges`.
+    ///
+    /// Edges are chosen one after another by backtracking. A branch is abandoned as soon as a
+    /// maximum matching of the remaining edges shows that they cannot complete it, so the work
+    /// depends on the number of perfect matchings instead of the number of combinations.
+    pub fn perfect_matchings<Output, Head, E>(edges: &[E], size: usize) -> Vec<Vec<usize>>
+    where
+        Output: PartialEq + Clone,
+        Head: PartialEq + Clone,
+        E: Edge<Output, Head>,
+    {
+        let mut outputs = InnerMap::default();
+        let mut heads = InnerMap::default();
+        let dense_edges: Vec<(usize, usize)> = edges
+            .iter()
+            .map(|e| {
+                let left = outputs.insert(e.left().clone());
+                let right = heads.insert(e.right().clone());
+                (left, right)
+            })
+            .collect();
+
+        let mut search = MatchingSearch {
+            edges: dense_edges,
+            size,
+            left_used: vec![false; outputs.list.len()],
+            right_used: vec![false; heads.list.len()],
+            chosen: vec![],
+            matchings: vec![],
+        };
+        search.descend(0);
+        search.matchings
+    }
+}
+
+/// Backtracking state of [`HopcroftKarpMap::perfect_matchings`].
+///
+/// Vertices are numbered densely on each side, so the pruning check can run on plain vectors.
+/// It is executed once per chosen edge, which makes it too hot for the hashing in
+/// `hopcroft_karp::matching`.
+struct MatchingSearch {
+    edges: Vec<(usize, usize)>,
+    size: usize,
+    left_used: Vec<bool>,
+    right_used: Vec<bool>,
+    // indices of the edges chosen so far
+    chosen: Vec<usize>,
+    matchings: Vec<Vec<usize>>,
+}
+
+impl MatchingSearch {
+    fn is_free(&self, (left, right): (usize, usize)) -> bool {
+        !self.left_used[left] && !self.right_used[right]
+    }
+
+    fn set_used(&mut self, (left, right): (usize, usize), used: bool) {
+        self.left_used[left] = used;
+        self.right_used[right] = used;
+    }
+
+    /// Returns true if the edges from `start` on can add `needed` edges to the chosen ones.
+    ///
+    /// Grows a matching of the free edges with augmenting paths until it is large enough.
+    fn can_complete(&self, start: usize, needed: usize) -> bool {
+        if needed == 0 {
+            return true;
+        }
+        let mut adjacent: Vec<Vec<usize>> = vec![vec![]; self.left_used.len()];
+        for (left, right) in self.edges[start..].iter().copied() {
+            if self.is_free((left, right)) {
+                adjacent[left].push(right);
+            }
+        }
+        let mut matched_left: Vec<Option<usize>> = vec![None; self.right_used.len()];
+        let mut found = 0;
+        for left in 0..adjacent.len() {
+            let mut visited = vec![false; self.right_used.len()];
+            if augment(left, &adjacent, &mut matched_left, &mut visited) {
+                found += 1;
+                if found == needed {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn descend(&mut self, start: usize) {
+        let needed = self.size - self.chosen.len();
+        if needed == 0 {
+            self.matchings.push(self.chosen.clone());
+            return;
+        }
+        for idx in start..self.edges.len() {
+            if self.edges.len() - idx < needed {
+                break;
+            }
+            let edge = self.edges[idx];
+            if !self.is_free(edge) {
+                continue;
+            }
+            self.chosen.push(idx);
+            self.set_used(edge, true);
+            if self.can_complete(idx + 1, needed - 1) {
+                self.descend(idx + 1);
+            }
+            self.set_used(edge, false);
+            self.chosen.pop();
+        }
+    }
+}
+
+/// Tries to match `left` by following an augmenting path.
+fn augment(
+    left: usize,
+    adjacent: &[Vec<usize>],
+    matched_left: &mut [Option<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for right in adjacent[left].iter().copied() {
+        if visited[right] {
+            continue;
+        }
+        visited[right] = true;
+        let free = match matched_left[right] {
+            None => true,
+            Some(other) => augment(other, adjacent, matched_left, visited),
+        };
+        if free {
+            matched_left[right] = Some(left);
+            return true;
+        }
+    }
+    false
 }
 
 struct CombinedMap<Output, Head, E>
@@ -193,3 +332,66 @@ impl Edge<Output, WlHead> for IntermediatePairingWithoutMode {
         &self.matched_head
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rstest::rstest;
+
+    use super::{Edge, HopcroftKarpMap};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct TestEdge(u8, char);
+
+    impl Edge<u8, char> for TestEdge {
+        fn left(&self) -> &u8 {
+            &self.0
+        }
+        fn right(&self) -> &char {
+            &self.1
+        }
+    }
+
+    /// Every `size`-element subset of edges that Hopcroft-Karp matches completely.
+    fn by_combinations(edges: &[TestEdge], size: usize) -> Vec<Vec<usize>> {
+        (0..edges.len())
+            .combinations(size)
+            .filter(|c| HopcroftKarpMap::hkmap(c.iter().map(|i| edges[*i].clone())).count() == size)
+            .collect()
+    }
+
+    fn complete(size: u8) -> Vec<TestEdge> {
+        (0..size)
+            .cartesian_product("abcdefgh".chars().take(size as usize))
+            .map(|(l, r)| TestEdge(l, r))
+            .collect()
+    }
+
+    #[rstest]
+    #[case(complete(1), 1)]
+    #[case(complete(3), 3)]
+    #[case(complete(4), 4)]
+    #[case(vec![TestEdge(0, 'a'), TestEdge(1, 'b'), TestEdge(2, 'c')], 3)]
+    #[case(vec![TestEdge(0, 'a'), TestEdge(0, 'b'), TestEdge(1, 'a'), TestEdge(2, 'b')], 3)]
+    #[case(vec![TestEdge(0, 'a'), TestEdge(0, 'b'), TestEdge(1, 'b'), TestEdge(1, 'c'), TestEdge(2, 'a'), TestEdge(2, 'c')], 3)]
+    #[case(vec![TestEdge(0, 'a'), TestEdge(1, 'a'), TestEdge(2, 'a')], 3)]
+    #[case(vec![], 0)]
+    fn perfect_matchings_equal_complete_combinations(
+        #[case] edges: Vec<TestEdge>,
+        #[case] size: usize,
+    ) {
+        assert_eq!(
+            HopcroftKarpMap::perfect_matchings(&edges, size),
+            by_combinations(&edges, size)
+        );
+    }
+
+    #[test]
+    fn perfect_matchings_count() {
+        // every permutation of 8 heads is a perfect matching
+        assert_eq!(
+            HopcroftKarpMap::perfect_matchings(&complete(8), 8).len(),
+            40320
+        );
+    }
+}
@@ -1,8 +1,8 @@
 use serde::{Deserialize, Serialize};
 
-use crate::profile::{Mode, Output};
+use crate::profile::{AdaptiveSyncState, Mode, Output, Position, Transform};
 use crate::search::SearchResult;
-use crate::wl_backend::{WlHead, WlMode};
+use crate::wl_backend::{WlGenericId, WlHead, WlMode};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Pairing {
@@ -46,6 +46,23 @@ pub struct IntermediatePairingWithoutMode {
     pub(crate) matched_head: WlHead,
 }
 
+/// The settings a [`Pairing`] applies to its head.
+///
+/// Pairings with equal configurations leave their heads in the same state, even if they
+/// originate from different outputs or profiles.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PairingConfig {
+    head: WlGenericId,
+    enable: bool,
+    mode: Option<WlGenericId>,
+    custom_mode: Option<Mode>,
+    position: Option<Position>,
+    // bits of the f64, it is only compared and never calculated with
+    scale: Option<u64>,
+    transform: Option<Transform>,
+    adaptive_sync: Option<AdaptiveSyncState>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UnrelatedPairing {
     pub(crate) output: Output,
@@ -96,6 +113,40 @@ impl Pairing {
             Pairing::WithoutMode(_) => 0,
         }
     }
+    /// Return the settings that are applied to the [`WlHead`].
+    ///
+    /// Mirrors what is sent to the compositor: a disabled head ignores all other settings.
+    pub fn config(&self) -> PairingConfig {
+        let output = self.output();
+        let head = self.wl_head().id;
+        if !output.enable {
+            return PairingConfig {
+                head,
+                enable: false,
+                mode: None,
+                custom_mode: None,
+                position: None,
+                scale: None,
+                transform: None,
+                adaptive_sync: None,
+            };
+        }
+        let custom_mode = output.mode.filter(|m| m.is_custom());
+        let mode = match (output.mode, custom_mode) {
+            (Some(_), None) => self.wl_mode().map(|m| m.id),
+            _ => None,
+        };
+        PairingConfig {
+            head,
+            enable: true,
+            mode,
+            custom_mode,
+            position: output.position,
+            scale: output.scale.map(f64::to_bits),
+            transform: output.transform,
+            adaptive_sync: output.adaptive_sync,
+        }
+    }
     /// Return how specific the [`Output`] matches to the [`WlHead`].
     /// Higher is better.
     pub fn specificity(&self) -> u64 {
@@ -106,6 +157,12 @@ impl Pairing {
     }
 }
 
+impl PairingConfig {
+    pub fn head(&self) -> WlGenericId {
+        self.head
+    }
+}
+
 impl IntermediatePairing {
     pub fn output(&self) -> &Output {
         match self {
@@ -1,24 +1,24 @@
 use std::collections::VecDeque;
+use std::sync::Arc;
 
-use itertools::Itertools;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use snafu::{prelude::*, Location};
 
 use crate::pipeline::PipeStage;
 use crate::profile::{Output, Profile};
-use crate::variant::ValidVariant;
 use crate::wl_backend::WlHead;
 
 use super::{
-    Comparator, ComparatorInfo, HopcroftKarpMap, IntermediatePairing, MatchReport, UnrelatedPairing,
+    Comparator, ComparatorInfo, HopcroftKarpMap, IntermediatePairing, MatchReport, SubsetVariants,
+    UnrelatedPairing,
 };
 
 /// Create initial pairings
 pub struct Stage1;
-/// Send combinations of pairings through Hopcroft-Karp
+/// Enumerate the perfect matchings of the pairings
 pub struct Stage2;
-/// Expand pairings to variants
+/// Prepare the lazy expansion of pairings to variants
 pub struct Stage3;
 
 /// Input for the matching pipeline
@@ -27,7 +27,7 @@ pub struct MatcherInput {
     pub(super) wl_heads: VecDeque<WlHead>,
     size: usize,
 
-    profile: Profile,
+    profile: Arc<Profile>,
     outputs: Vec<Output>,
 }
 /// Data transfer between stage 1 and stage 2
@@ -61,10 +61,11 @@ pub struct MatcherOutput {
     unpaired_outputs: Vec<Output>,
     unrelated_pairings: Vec<UnrelatedPairing>,
     // from stage 2
-    // contains lists of pairings where [pairing].len() != wl_heads.len()
+    // contains a maximum matching where [pairing].len() != wl_heads.len(),
+    // if there is no valid subset
     invalid_subsets: Vec<Vec<IntermediatePairing>>,
     // from stage 3
-    valid_variants: VecDeque<ValidVariant>,
+    valid_subsets: Vec<SubsetVariants>,
 }
 
 impl PipeStage for Stage1 {
@@ -104,15 +105,22 @@ impl PipeStage for Stage2 {
 
     fn process(mut input: Self::Input) -> Result<Self::Output, Self::Error> {
         info!("stage 2");
-        let (valid_subsets, invalid_subsets): (Vec<Vec<_>>, Vec<Vec<_>>) =
-            std::mem::take(&mut input.intermediate_pairings)
+        let ipairs = std::mem::take(&mut input.intermediate_pairings);
+        let valid_subsets: Vec<Vec<_>> =
+            HopcroftKarpMap::perfect_matchings(&ipairs, input.initial.size)
                 .into_iter()
-                // Create k-element subsets of intermediate pairings, with k == wl_heads.len()
-                .combinations(input.initial.size)
-                .map(|ipairs| ipairs.into_iter())
+                .map(|matching| matching.into_iter().map(|idx| ipairs[idx].clone()))
                 .map(HopcroftKarpMap::hkmap)
-                .map(|i| i.collect::<Vec<_>>())
-                .partition(|ipairs| ipairs.len() == input.initial.size);
+                .map(|i| i.collect())
+                .collect();
+        debug!("len(valid subsets)={:?}", valid_subsets.len());
+
+        // Without any perfect matching, keep the best partial one to show what is missing.
+        let invalid_subsets = if valid_subsets.is_empty() {
+            vec![HopcroftKarpMap::hkmap(ipairs.into_iter()).collect()]
+        } else {
+            vec![]
+        };
 
         if valid_subsets.is_empty() {
             let transfer = input.enrich(valid_subsets, invalid_subsets);
@@ -130,33 +138,24 @@ impl PipeStage for Stage3 {
 
     fn process(mut input: Self::Input) -> Result<Self::Output, Self::Error> {
         info!("stage 3");
-        let valid_variants: VecDeque<ValidVariant> = std::mem::take(&mut input.valid_subsets)
+        let profile = &input.initial.profile;
+        // the variants of all subsets of the profile are numbered consecutively
+        let mut offset = 0;
+        let valid_subsets: Vec<SubsetVariants> = std::mem::take(&mut input.valid_subsets)
             .into_iter()
             .map(|ipairs| {
-                ipairs
-                    .into_iter()
-                    .map(|ipair| ipair.expand())
-                    .multi_cartesian_product()
-            })
-            .enumerate()
-            .flat_map(|(idx, pairs)| {
-                pairs.into_iter().map(move |p| (idx, p)).enumerate().map(
-                    |(jdx, (idx, pairings))| ValidVariant {
-                        profile: input.initial.profile.clone(),
-                        pairings,
-                        state: Default::default(),
-                        index: (idx * input.initial.size + jdx),
-                    },
-                )
+                let subset = SubsetVariants::new(Arc::clone(profile), offset, ipairs);
+                offset = offset.saturating_add(subset.len());
+                subset
             })
             .collect();
 
-        Ok(input.enrich(valid_variants))
+        Ok(input.enrich(valid_subsets))
     }
 }
 
 impl MatcherInput {
-    pub fn new(wl_heads: VecDeque<WlHead>, profile: Profile) -> Self {
+    pub fn new(wl_heads: VecDeque<WlHead>, profile: Arc<Profile>) -> Self {
         let size = wl_heads.len();
         Self {
             wl_heads,
@@ -192,14 +191,14 @@ impl TransferOneTwo {
     }
 }
 impl TransferTwoThree {
-    fn enrich(self, valid_variants: VecDeque<ValidVariant>) -> MatcherOutput {
+    fn enrich(self, valid_subsets: Vec<SubsetVariants>) -> MatcherOutput {
         MatcherOutput {
             initial: self.initial,
             unpaired_heads: self.unpaired_heads,
             unpaired_outputs: self.unpaired_outputs,
             unrelated_pairings: self.unrelated_pairings,
             invalid_subsets: self.invalid_subsets,
-            valid_variants,
+            valid_subsets,
         }
     }
 }
@@ -212,7 +211,7 @@ impl MatcherOutput {
             unpaired_outputs: self.unpaired_outputs,
             unrelated_pairings: self.unrelated_pairings,
             invalid_subsets: self.invalid_subsets,
-            valid_variants: self.valid_variants,
+            valid_subsets: self.valid_subsets,
         }
     }
 }
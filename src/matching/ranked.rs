@@ -0,0 +1,439 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::profile::Profile;
+use crate::variant::ValidVariant;
+
+use super::{IntermediatePairing, MatchReport, Pairing, PairingConfig};
+
+/// All variants that can be built from one valid subset of pairings.
+///
+/// Every output of the subset can be configured with one of several pairings (one per matched
+/// mode). The pairings of each output are sorted by their mode deviation, so the variants can be
+/// enumerated in ranked order without building all of them.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubsetVariants {
+    profile: Arc<Profile>,
+    /// Number of variants of the earlier subsets of the same [`MatchReport`], the index of the
+    /// first variant of this subset
+    offset: usize,
+    specificity: u64,
+    /// Pairings per output, together with their position in the unsorted list of modes
+    choices: Vec<Vec<(usize, Pairing)>>,
+}
+
+/// Iterator over the variants of several [`MatchReport`]s, best variant first.
+///
+/// Variants are ranked by specificity (decreasing) and mode deviation (increasing). Equally
+/// ranked variants keep the order of the reports, the subsets and the modes. Only the variants
+/// that have been requested so far (and their direct successors) are ever built.
+///
+/// A variant that configures every head like a variant of the same profile returned before is
+/// skipped, as testing it again cannot give a different result. Other profiles may run other
+/// commands for the same configuration, so their variants are kept.
+#[derive(Clone, Debug, Default)]
+pub struct RankedVariants {
+    sources: Vec<SubsetVariants>,
+    candidates: BinaryHeap<Reverse<Candidate>>,
+    // profile index and configuration of the variants returned so far
+    seen: HashSet<(usize, Vec<PairingConfig>)>,
+    returned: usize,
+    duplicates: usize,
+    limit: Option<usize>,
+}
+
+/// A not yet built variant of a [`SubsetVariants`].
+///
+/// The derived ordering is the ranking of the variants.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Candidate {
+    specificity: Reverse<u64>,
+    deviation: u32,
+    source: usize,
+    /// Position of each chosen mode in the unsorted list of modes
+    original: Vec<usize>,
+    /// Position of each chosen mode in the sorted list of modes
+    positions: Vec<usize>,
+    /// Successors only advance outputs from this one on, which visits every variant once.
+    last: usize,
+}
+
+impl SubsetVariants {
+    pub(super) fn new(
+        profile: Arc<Profile>,
+        offset: usize,
+        ipairs: Vec<IntermediatePairing>,
+    ) -> Self {
+        let choices: Vec<Vec<(usize, Pairing)>> = ipairs
+            .into_iter()
+            .map(|ipair| {
+                let mut pairings: Vec<_> = ipair.expand().into_iter().enumerate().collect();
+                pairings.sort_by_key(|(idx, p)| (p.mode_deviation(), *idx));
+                pairings
+            })
+            .collect();
+        let specificity = match choices.len() {
+            0 => 0,
+            n => {
+                choices
+                    .iter()
+                    .filter_map(|c| c.first())
+                    .map(|(_, p)| p.specificity())
+                    .sum::<u64>()
+                    / n as u64
+            }
+        };
+        Self {
+            profile,
+            offset,
+            specificity,
+            choices,
+        }
+    }
+
+    /// Returns the number of variants.
+    pub fn len(&self) -> usize {
+        if self.choices.is_empty() {
+            return 0;
+        }
+        self.choices
+            .iter()
+            .map(|c| c.len())
+            .fold(1, usize::saturating_mul)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn candidate(&self, source: usize, positions: Vec<usize>, last: usize) -> Candidate {
+        let chosen = || {
+            self.choices
+                .iter()
+                .zip(positions.iter())
+                .map(|(c, p)| &c[*p])
+        };
+        Candidate {
+            specificity: Reverse(self.specificity),
+            deviation: chosen().map(|(_, p)| p.mode_deviation()).sum(),
+            source,
+            original: chosen().map(|(idx, _)| *idx).collect(),
+            positions,
+            last,
+        }
+    }
+
+    fn build(&self, candidate: &Candidate) -> ValidVariant {
+        let pairings = self
+            .choices
+            .iter()
+            .zip(candidate.positions.iter())
+            .map(|(c, p)| c[*p].1.clone())
+            .collect();
+        // rank of the variant in the cartesian product of all modes
+        let rank = self
+            .choices
+            .iter()
+            .zip(candidate.original.iter())
+            .fold(0, |rank, (c, idx)| rank * c.len() + idx);
+        ValidVariant {
+            profile: Arc::clone(&self.profile),
+            pairings,
+            state: Default::default(),
+            index: self.offset + rank,
+        }
+    }
+}
+
+impl RankedVariants {
+    pub fn new(reports: &VecDeque<MatchReport>) -> Self {
+        let sources: Vec<SubsetVariants> = reports
+            .iter()
+            .flat_map(|r| r.valid_subsets.iter().cloned())
+            .filter(|s| !s.is_empty())
+            .collect();
+        let candidates = sources
+            .iter()
+            .enumerate()
+            .map(|(idx, s)| Reverse(s.candidate(idx, vec![0; s.choices.len()], 0)))
+            .collect();
+        Self {
+            sources,
+            candidates,
+            seen: Default::default(),
+            returned: 0,
+            duplicates: 0,
+            limit: None,
+        }
+    }
+
+    /// Stops after `limit` variants, which are the best ranked ones.
+    pub fn limit(mut self, limit: Option<usize>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Returns true if no more variants are returned because the limit has been reached.
+    pub fn is_limit_reached(&self) -> bool {
+        self.limit.is_some_and(|l| self.returned >= l)
+    }
+
+    /// Returns the number of variants skipped so far because they were duplicates.
+    pub fn duplicate_count(&self) -> usize {
+        self.duplicates
+    }
+
+    /// Returns the next variant in ranked order, including duplicates.
+    fn next_ranked(&mut self) -> Option<ValidVariant> {
+        let Reverse(candidate) = self.candidates.pop()?;
+        let source = &self.sources[candidate.source];
+        for output in candidate.last..source.choices.len() {
+            if candidate.positions[output] + 1 < source.choices[output].len() {
+                let mut positions = candidate.positions.clone();
+                positions[output] += 1;
+                let successor = source.candidate(candidate.source, positions, output);
+                self.candidates.push(Reverse(successor));
+            }
+        }
+        Some(source.build(&candidate))
+    }
+
+    /// Returns the variant with these indices, see [`ValidVariant::idx_str`].
+    ///
+    /// Duplicates are found as well, the limit is ignored.
+    pub fn find(mut self, profile_index: usize, index: usize) -> Option<ValidVariant> {
+        std::iter::from_fn(|| self.next_ranked())
+            .find(|v| v.profile.index == profile_index && v.index == index)
+    }
+
+    /// Returns the total number of variants, including the ones already returned and the
+    /// duplicates that will be skipped.
+    pub fn total(&self) -> usize {
+        self.sources
+            .iter()
+            .map(|s| s.len())
+            .fold(0, usize::saturating_add)
+    }
+}
+
+impl Iterator for RankedVariants {
+    type Item = ValidVariant;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_limit_reached() {
+            return None;
+        }
+        loop {
+            let variant = self.next_ranked()?;
+            if self.seen.insert((variant.profile.index, variant.config())) {
+                self.returned += 1;
+                return Some(variant);
+            }
+            self.duplicates += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use itertools::Itertools;
+
+    use crate::matching::ProfileMatcher;
+    use crate::settings::SettingsToml;
+    use crate::variant::ValidVariant;
+    use crate::wl_backend::test_heads::head;
+    use crate::wl_backend::WlHead;
+
+    use super::RankedVariants;
+
+    fn reports(config: &str, heads: &VecDeque<WlHead>) -> VecDeque<crate::matching::MatchReport> {
+        let mut config: SettingsToml = toml::from_str(config).unwrap();
+        config
+            .profiles
+            .iter_mut()
+            .enumerate()
+            .for_each(|(idx, p)| p.index = idx);
+        config
+            .profiles
+            .into_iter()
+            .filter_map(|p| ProfileMatcher::create_report(p, heads.clone()))
+            .collect()
+    }
+
+    /// Builds and sorts every variant up front.
+    fn eager(reports: &VecDeque<crate::matching::MatchReport>) -> Vec<ValidVariant> {
+        let mut variants: Vec<ValidVariant> = reports
+            .iter()
+            .flat_map(|r| {
+                r.valid_subsets.iter().flat_map(|s| {
+                    s.choices
+                        .iter()
+                        .map(|c| {
+                            let mut c = c.clone();
+                            c.sort_by_key(|(idx, _)| *idx);
+                            c.into_iter().map(|(_, p)| p)
+                        })
+                        .multi_cartesian_product()
+                        .enumerate()
+                        .map(|(jdx, pairings)| ValidVariant {
+                            profile: s.profile.clone(),
+                            pairings,
+                            state: Default::default(),
+                            index: s.offset + jdx,
+                        })
+                })
+            })
+            .collect();
+        variants.sort_by(|a, b| {
+            (b.specificity(), a.mode_deviation()).cmp(&(a.specificity(), b.mode_deviation()))
+        });
+        variants
+    }
+
+    #[test]
+    fn lazy_ranking_equals_eager_ranking() {
+        let heads: VecDeque<WlHead> = vec![
+            head(1, "DP-1", &[60000, 59950, 60300, 59700]),
+            head(2, "DP-2", &[60000, 60100, 59900]),
+            head(3, "HDMI-A-1", &[60000, 60000, 59990]),
+        ]
+        .into();
+        let config = r#"
+            [[profile]]
+            name = "broad"
+            output = [
+                { search = "/DP", enable = true, mode = "1920x1080@60Hz" },
+                { search = "%-", enable = true, mode = "1920x1080@60Hz" },
+                { search = "/.", enable = true, mode = "1920x1080@60Hz" },
+            ]
+            [[profile]]
+            name = "specific"
+            output = [
+                { search = "n=DP-1", enable = true, mode = "1920x1080@60Hz" },
+                { search = "n=DP-2", enable = true, mode = "1920x1080@59.9Hz" },
+                { search = "n%HDMI", enable = true, mode = "1920x1080" },
+            ]
+        "#;
+        let reports = reports(config, &heads);
+        let mut ranked = RankedVariants::new(&reports);
+        assert_eq!(ranked.total(), eager(&reports).len());
+
+        let key = |v: &ValidVariant| (v.idx_str(), v.pairings.clone());
+        let lazy: Vec<_> = std::iter::from_fn(|| ranked.next_ranked())
+            .map(|v| key(&v))
+            .collect();
+        let eager: Vec<_> = eager(&reports).iter().map(key).collect();
+        assert!(!lazy.is_empty());
+        assert_eq!(lazy, eager);
+    }
+
+    #[test]
+    fn first_variant_without_building_all() {
+        let heads: VecDeque<WlHead> = (0..4)
+            .map(|id| head(id, &format!("DP-{id}"), &[60000; 40]))
+            .collect();
+        let config = r#"
+            [[profile]]
+            name = "many modes"
+            output = [
+                { search = "n=DP-0", enable = true, mode = "1920x1080" },
+                { search = "n=DP-1", enable = true, mode = "1920x1080" },
+                { search = "n=DP-2", enable = true, mode = "1920x1080" },
+                { search = "n=DP-3", enable = true, mode = "1920x1080" },
+            ]
+        "#;
+        let mut ranked = RankedVariants::new(&reports(config, &heads));
+        assert_eq!(ranked.total(), 40 * 40 * 40 * 40);
+        let first = ranked.next().unwrap();
+        assert_eq!(first.index, 0);
+        assert!(ranked.candidates.len() <= 4);
+    }
+
+    #[test]
+    fn skip_duplicate_variants() {
+        let heads: VecDeque<WlHead> =
+            vec![head(1, "DP-1", &[60000]), head(2, "DP-2", &[60000])].into();
+        // Both outputs fit both heads and have the same settings, so swapping them changes
+        // nothing. The second profile repeats the configuration of the first one but is another
+        // profile, so it is kept.
+        let config = r#"
+            [[profile]]
+            name = "interchangeable"
+            output = [
+                { search = "/DP", enable = true, mode = "1920x1080" },
+                { search = "%DP", enable = true, mode = "1920x1080" },
+            ]
+            [[profile]]
+            name = "same"
+            output = [
+                { search = "n=DP-1", enable = true, mode = "1920x1080" },
+                { search = "n=DP-2", enable = true, mode = "1920x1080" },
+            ]
+            [[profile]]
+            name = "different"
+            output = [
+                { search = "n=DP-1", enable = true, mode = "1920x1080" },
+                { search = "n=DP-2", enable = false },
+            ]
+        "#;
+        let mut ranked = RankedVariants::new(&reports(config, &heads));
+        assert_eq!(ranked.total(), 4);
+        let names: Vec<_> = ranked.by_ref().map(|v| v.profile.name.clone()).collect();
+        assert_eq!(names, vec!["same", "different", "interchangeable"]);
+        assert_eq!(ranked.duplicate_count(), 1);
+    }
+
+    #[test]
+    fn keep_equal_variants_of_other_profiles() {
+        let heads: VecDeque<WlHead> = vec![head(1, "DP-1", &[60000, 50000])].into();
+        let config = r#"
+            [[profile]]
+            name = "quiet"
+            output = [{ search = "n=DP-1", enable = true, mode = "1920x1080" }]
+            [[profile]]
+            name = "loud"
+            exec = ["notify-send loud"]
+            output = [{ search = "n=DP-1", enable = true, mode = "1920x1080" }]
+        "#;
+        let reports = reports(config, &heads);
+        let ranked = RankedVariants::new(&reports);
+        let variants: Vec<_> = ranked.map(|v| v.idx_str()).collect();
+        assert_eq!(variants, ["0,0", "0,1", "1,0", "1,1"]);
+        // the limit counts the returned variants of all profiles
+        let ranked = RankedVariants::new(&reports).limit(Some(3));
+        let names: Vec<_> = ranked.map(|v| v.profile.name.clone()).collect();
+        assert_eq!(names, ["quiet", "quiet", "loud"]);
+    }
+
+    #[test]
+    fn variant_indices_are_unique() {
+        let heads: VecDeque<WlHead> = vec![
+            head(1, "DP-1", &[60000, 59940, 50000]),
+            head(2, "DP-2", &[60000, 59940, 50000]),
+        ]
+        .into();
+        // both outputs fit both heads, each of the two subsets has 9 variants
+        let config = r#"
+            [[profile]]
+            name = "side by side"
+            output = [
+                { search = "/DP", enable = true, mode = "1920x1080", position = "0,0" },
+                { search = "/DP", enable = true, mode = "1920x1080", position = "1920,0" },
+            ]
+        "#;
+        let reports = reports(config, &heads);
+        assert_eq!(reports[0].valid_subsets.len(), 2);
+        let mut indices: Vec<_> = RankedVariants::new(&reports).map(|v| v.index).collect();
+        indices.sort();
+        assert_eq!(indices, (0..18).collect::<Vec<_>>());
+        for index in [2, 9, 17] {
+            let variant = RankedVariants::new(&reports).find(0, index).unwrap();
+            assert_eq!(variant.index, index);
+        }
+    }
+}
@@ -12,7 +12,7 @@ use snafu::prelude::*;
 use crate::search::Search;
 
 pub use self::convert::{ConvertError, Converter, ConverterSettings};
-pub use self::mode::Mode;
+pub use self::mode::{Mode, ParseModeError};
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Profile {
@@ -21,6 +21,8 @@ pub struct Profile {
     pub index: usize,
     #[serde(rename = "exec")]
     pub commands: Option<Vec<String>>,
+    /// Overrides the global `confirm_timeout`
+    pub confirm_timeout: Option<u64>,
     // table must come last in toml
     #[serde(rename = "output")]
     pub outputs: Vec<Output>,
@@ -46,19 +48,19 @@ pub struct PhysicalSize {
     pub height: i32,
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum AdaptiveSyncState {
     Disabled,
     Enabled,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Transform {
     #[serde(rename = "normal")]
     Normal,
@@ -84,6 +86,7 @@ impl Profile {
             name,
             outputs,
             commands: Default::default(),
+            confirm_timeout: Default::default(),
             index: Default::default(),
         }
     }
@@ -1,13 +1,15 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::str::FromStr;
 
 use itertools::Itertools;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
 use snafu::{prelude::*, Location};
 
 use crate::profile::{Output, Profile};
 use crate::search::{MultiSearch, ParseSingleSearchError, SearchField, SingleSearch};
 use crate::settings::SettingsToml;
-use crate::wl_backend::WlHead;
+use crate::wl_backend::{WlGenericId, WlHead};
 
 #[derive(Debug, Snafu)]
 #[snafu(context(suffix(Ctx)))]
@@ -50,22 +52,31 @@ impl ConverterSettings {
 }
 impl Converter {
     pub fn run(&self, heads: VecDeque<WlHead>) -> Result<String, ConvertError> {
-        let mut outputs: Vec<Output> = vec![];
-        for head in heads {
-            outputs.push(self.convert_head_to_output(head)?);
-        }
-
-        let p = Profile::new(self.settings.profile_name.clone(), outputs);
+        let p = self.profile(heads)?;
         let sc = SettingsToml {
-            timeout: None,
             profiles: vec![p].into(),
+            ..Default::default()
         };
         let settings_string = toml::to_string(&sc).context(TomlSerializeCtx)?;
         Ok(settings_string)
     }
-    fn convert_head_to_output(&self, head: WlHead) -> Result<Output, ConvertError> {
+    /// Converts the heads into a profile reproducing their current state.
+    pub fn profile(&self, heads: VecDeque<WlHead>) -> Result<Profile, ConvertError> {
+        let ambiguous_heads = ambiguous_heads(&heads);
+        let mut outputs: Vec<Output> = vec![];
+        for head in heads {
+            let fallback = ambiguous_heads.get(&head.id).copied();
+            outputs.push(self.convert_head_to_output(head, fallback)?);
+        }
+        Ok(Profile::new(self.settings.profile_name.clone(), outputs))
+    }
+    fn convert_head_to_output(
+        &self,
+        head: WlHead,
+        fallback: Option<SearchField>,
+    ) -> Result<Output, ConvertError> {
         let ms = self
-            .multi_search_from_head(&head)
+            .multi_search_from_head(&head, fallback)
             .context(SingleSearchCtx { head: head.clone() })?;
         let search_pattern = ms.into();
 
@@ -89,30 +100,79 @@ impl Converter {
         Ok(output)
     }
 
-    fn multi_search_from_head(&self, head: &WlHead) -> Result<MultiSearch, ParseSingleSearchError> {
-        let searches: Result<Vec<SingleSearch>, ParseSingleSearchError> = self
-            .settings
-            .included_search_fields
+    fn multi_search_from_head(
+        &self,
+        head: &WlHead,
+        fallback: Option<SearchField>,
+    ) -> Result<MultiSearch, ParseSingleSearchError> {
+        let mut fields = self.settings.included_search_fields.clone();
+        if let Some(field) = fallback.filter(|f| !fields.contains(f)) {
+            debug!(
+                "including the {} of head {:?} to tell it apart",
+                field.as_str(),
+                head.name()
+            );
+            fields.push(field);
+        }
+        let searches: Result<Vec<SingleSearch>, ParseSingleSearchError> = fields
             .iter()
             .unique()
-            .map(|field| match field {
-                SearchField::Description => {
-                    SingleSearch::from_str(&format!("d={}", head.description()))
+            // Heads without an EDID cannot be searched by EDID fields
+            .filter_map(|field| match field {
+                SearchField::Description => Some(format!("d={}", head.description())),
+                SearchField::Name => Some(format!("n={}", head.name())),
+                SearchField::Vendor => Some(format!("v={}", head.make())),
+                SearchField::Model => Some(format!("m={}", head.model())),
+                SearchField::Serial => Some(format!("s={}", head.serial_number())),
+                SearchField::EdidProduct => {
+                    let edid = head.edid()?;
+                    Some(format!("p={}", edid.product_code()))
+                }
+                SearchField::EdidYear => {
+                    let edid = head.edid()?;
+                    Some(format!("y={}", edid.manufacture_year()))
                 }
-                SearchField::Name => SingleSearch::from_str(&format!("n={}", head.name())),
-                SearchField::Vendor => SingleSearch::from_str(&format!("v={}", head.make())),
-                SearchField::Model => SingleSearch::from_str(&format!("m={}", head.model())),
-                SearchField::Serial => {
-                    SingleSearch::from_str(&format!("s={}", head.serial_number()))
+                SearchField::EdidHash => {
+                    let edid = head.edid()?;
+                    Some(format!("e={}", edid.hash_str()))
                 }
+                // Capabilities describe a class of displays, not a specific one
+                SearchField::Width | SearchField::Height | SearchField::Mode => None,
             })
+            .map(|s| SingleSearch::from_str(&s))
             .collect();
         Ok(MultiSearch::new(searches?))
     }
 }
 
+/// Collect the ids of all heads that share their vendor, model and serial number with another
+/// head, together with the field that tells them apart.
+///
+/// Identical displays often report an empty serial number. Searches for these heads cannot tell
+/// them apart, so the hash of the EDID is used as a fallback. Unlike the name of the connector,
+/// it stays the same if the display is plugged in elsewhere. The name is used if there is no
+/// EDID or another head has the same one.
+fn ambiguous_heads(heads: &VecDeque<WlHead>) -> HashMap<WlGenericId, SearchField> {
+    let counts = heads
+        .iter()
+        .counts_by(|h| (h.make(), h.model(), h.serial_number()));
+    let hash_counts = heads
+        .iter()
+        .filter_map(|h| h.edid().map(|edid| edid.hash()))
+        .counts();
+    heads
+        .iter()
+        .filter(|h| counts[&(h.make(), h.model(), h.serial_number())] > 1)
+        .map(|h| match h.edid() {
+            Some(edid) if hash_counts[&edid.hash()] == 1 => (h.id, SearchField::EdidHash),
+            _ => (h.id, SearchField::Name),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::VecDeque;
     use std::str::FromStr;
 
     use rstest::rstest;
@@ -120,6 +180,49 @@ mod tests {
     use crate::profile::{Mode, Output, Profile};
     use crate::search::MultiSearch;
     use crate::search::SingleSearch;
+    use crate::search::{SearchField, SearchField::Name};
+    use crate::wl_backend::test_heads::head_with_base;
+    use crate::wl_backend::{Edid, WlBaseHead, WlHead};
+
+    use super::ConverterSettings;
+
+    fn head(id: usize, name: &str, serial: &str) -> WlHead {
+        let base = WlBaseHead {
+            name: name.to_string(),
+            make: "Foo Inc.".to_string(),
+            model: "FooHD-24".to_string(),
+            serial_number: serial.to_string(),
+            ..Default::default()
+        };
+        head_with_base(id, base, &[])
+    }
+
+    /// An identical head without a serial number, whose EDID differs by the given byte.
+    fn edid_head(id: usize, name: &str, edid: Option<u8>) -> WlHead {
+        let edid = edid.map(|serial| {
+            let mut blob = [0; 128];
+            blob[..8].copy_from_slice(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+            blob[12] = serial;
+            Edid::parse(&blob).unwrap()
+        });
+        let base = WlBaseHead {
+            name: name.to_string(),
+            make: "Foo Inc.".to_string(),
+            model: "FooHD-24".to_string(),
+            edid,
+            ..Default::default()
+        };
+        head_with_base(id, base, &[])
+    }
+
+    fn export(heads: VecDeque<WlHead>, fields: Vec<SearchField>) -> String {
+        ConverterSettings::default()
+            .profile_name("foo".into())
+            .include_search_fields(fields)
+            .converter()
+            .run(heads)
+            .unwrap()
+    }
 
     #[rstest]
     fn ser_multi_search() {
@@ -152,4 +255,40 @@ mode = "best"
         println!("{}", s);
         assert_eq!(test, s);
     }
+
+    #[rstest]
+    #[case(vec![head(1, "DP-1", ""), head(2, "DP-2", "")], vec![], 2)]
+    #[case(vec![head(1, "DP-1", "123"), head(2, "DP-2", "123")], vec![], 2)]
+    #[case(vec![head(1, "DP-1", "123"), head(2, "DP-2", "456")], vec![], 0)]
+    #[case(vec![head(1, "DP-1", ""), head(2, "DP-2", ""), head(3, "DP-3", "1")], vec![], 2)]
+    #[case(vec![head(1, "DP-1", ""), head(2, "DP-2", "")], vec![Name], 2)]
+    #[case(vec![head(1, "DP-1", "")], vec![], 0)]
+    fn export_includes_name_of_ambiguous_heads(
+        #[case] heads: Vec<WlHead>,
+        #[case] extra_fields: Vec<SearchField>,
+        #[case] name_searches: usize,
+    ) {
+        let mut fields = vec![SearchField::Model, SearchField::Serial, SearchField::Vendor];
+        fields.extend(extra_fields.iter());
+        let s = export(heads.into(), fields);
+        println!("{}", s);
+        assert_eq!(s.matches("\"n=DP-").count(), name_searches);
+    }
+
+    #[rstest]
+    #[case(vec![edid_head(1, "DP-1", Some(1)), edid_head(2, "DP-2", Some(2))], 2, 0)]
+    #[case(vec![edid_head(1, "DP-1", Some(1)), edid_head(2, "DP-2", Some(1))], 0, 2)]
+    #[case(vec![edid_head(1, "DP-1", Some(1)), edid_head(2, "DP-2", None)], 1, 1)]
+    #[case(vec![edid_head(1, "DP-1", None), edid_head(2, "DP-2", None)], 0, 2)]
+    fn export_prefers_edid_hash_of_ambiguous_heads(
+        #[case] heads: Vec<WlHead>,
+        #[case] hash_searches: usize,
+        #[case] name_searches: usize,
+    ) {
+        let fields = vec![SearchField::Model, SearchField::Serial, SearchField::Vendor];
+        let s = export(heads.into(), fields);
+        println!("{}", s);
+        assert_eq!(s.matches("\"e=").count(), hash_searches);
+        assert_eq!(s.matches("\"n=DP-").count(), name_searches);
+    }
 }
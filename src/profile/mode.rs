@@ -8,7 +8,7 @@ use snafu::{prelude::*, ResultExt};
 
 use crate::wl_backend::WlMode;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum Mode {
     Best,
     Preferred,
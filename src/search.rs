@@ -1,23 +1,45 @@
+mod any;
+mod diagnostic;
 mod field;
 mod multi;
 mod parser;
 mod query;
 mod single;
 
+use std::collections::VecDeque;
 use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
+use crate::matching::MAX_RR_DEVIATION;
+use crate::profile::{Mode, PhysicalSize};
+use crate::wl_backend::{Edid, WlMode};
+
+pub use self::any::{AnyQuery, AnySearch, AnySearchResult};
+pub use self::diagnostic::{diagnose, SearchDiagnostic, Severity};
 pub use self::field::{FieldSet, FieldSetError, SearchField};
 pub use self::multi::{MultiQuery, MultiSearch, MultiSearchResult};
 pub use self::parser::ParseSingleSearchError;
 pub use self::query::SingleQuery;
 pub use self::single::{SingleSearch, SingleSearchResult};
 
+/// Weight of numeric comparisons and mode capabilities.
+///
+/// A predicate is satisfied by a range of displays, so it ranks below a full text match.
+/// It still ranks above a regex match.
+const PREDICATE_WEIGHT: f64 = 512.0;
+/// Weight of a regex match, the lowest weight of all matches.
+const REGEX_WEIGHT: f64 = 1.0;
+
+/// Makes a full text, substring or glob search ignore the case when placed in front of the
+/// search kind.
+pub const CASE_INSENSITIVE_MODIFIER: char = '~';
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum SearchResult {
     Single(SingleSearchResult),
     Multi(MultiSearchResult),
+    Any(AnySearchResult),
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -25,12 +47,14 @@ pub enum SearchResult {
 pub enum Search {
     Single(SingleSearch),
     Multi(MultiSearch),
+    Any(AnySearch),
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Query<'a> {
     Single(SingleQuery<'a>),
     Multi(MultiQuery<'a>),
+    Any(AnyQuery<'a>),
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialOrd, Ord, PartialEq, Eq)]
@@ -39,6 +63,9 @@ pub enum SearchKind {
     Substring,
     #[default]
     Fulltext,
+    Glob,
+    Less,
+    Greater,
 }
 
 #[derive(Clone, Debug)]
@@ -46,6 +73,19 @@ pub enum SearchPattern {
     Regex(regex::Regex),
     Substring(String),
     Fulltext(String),
+    Glob(String),
+    /// Wraps a lowercase pattern and compares it with the lowercase text.
+    CaseInsensitive(Box<SearchPattern>),
+    Compare(Comparison, i64),
+    Mode(Mode),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
@@ -62,6 +102,7 @@ impl Search {
         match self {
             Search::Single(s) => s.query().into(),
             Search::Multi(s) => s.query().into(),
+            Search::Any(s) => s.query().into(),
         }
     }
 }
@@ -71,12 +112,26 @@ impl SearchResult {
         match self {
             SearchResult::Single(sr) => sr.is_ok(),
             SearchResult::Multi(sr) => sr.is_ok(),
+            SearchResult::Any(sr) => sr.is_ok(),
         }
     }
     pub fn specificity(&self) -> u64 {
         match self {
             SearchResult::Single(sr) => sr.specificity(),
             SearchResult::Multi(sr) => sr.specificity(),
+            SearchResult::Any(sr) => sr.specificity(),
+        }
+    }
+    /// Returns the inner results that are responsible for this result not being ok.
+    pub fn failed_searches(&self) -> Vec<&SingleSearchResult> {
+        if self.is_ok() {
+            return vec![];
+        }
+        match self {
+            SearchResult::Single(sr) => vec![sr],
+            SearchResult::Multi(sr) => sr.results().iter().filter(|r| !r.is_ok()).collect(),
+            // every alternative failed
+            SearchResult::Any(sr) => sr.results().iter().collect(),
         }
     }
 }
@@ -86,42 +141,70 @@ impl<'a> Query<'a> {
         match search {
             Search::Single(search) => search.query().into(),
             Search::Multi(search) => search.query().into(),
+            Search::Any(search) => search.query().into(),
         }
     }
     pub fn description(self, description: &'a str) -> Self {
         match self {
             Query::Single(q) => q.description(description).into(),
             Query::Multi(q) => q.description(description).into(),
+            Query::Any(q) => q.description(description).into(),
         }
     }
     pub fn model(self, model: &'a str) -> Self {
         match self {
             Query::Single(q) => q.model(model).into(),
             Query::Multi(q) => q.model(model).into(),
+            Query::Any(q) => q.model(model).into(),
         }
     }
     pub fn name(self, name: &'a str) -> Self {
         match self {
             Query::Single(q) => q.name(name).into(),
             Query::Multi(q) => q.name(name).into(),
+            Query::Any(q) => q.name(name).into(),
         }
     }
     pub fn serial(self, serial: &'a str) -> Self {
         match self {
             Query::Single(q) => q.serial(serial).into(),
             Query::Multi(q) => q.serial(serial).into(),
+            Query::Any(q) => q.serial(serial).into(),
         }
     }
     pub fn vendor(self, vendor: &'a str) -> Self {
         match self {
             Query::Single(q) => q.vendor(vendor).into(),
             Query::Multi(q) => q.vendor(vendor).into(),
+            Query::Any(q) => q.vendor(vendor).into(),
+        }
+    }
+    pub fn edid(self, edid: Option<&'a Edid>) -> Self {
+        match self {
+            Query::Single(q) => q.edid(edid).into(),
+            Query::Multi(q) => q.edid(edid).into(),
+            Query::Any(q) => q.edid(edid).into(),
+        }
+    }
+    pub fn size(self, size: PhysicalSize) -> Self {
+        match self {
+            Query::Single(q) => q.size(size).into(),
+            Query::Multi(q) => q.size(size).into(),
+            Query::Any(q) => q.size(size).into(),
+        }
+    }
+    pub fn modes(self, modes: &'a VecDeque<WlMode>) -> Self {
+        match self {
+            Query::Single(q) => q.modes(modes).into(),
+            Query::Multi(q) => q.modes(modes).into(),
+            Query::Any(q) => q.modes(modes).into(),
         }
     }
     pub fn run(self) -> SearchResult {
         match self {
             Query::Single(q) => q.run().into(),
             Query::Multi(q) => q.run().into(),
+            Query::Any(q) => q.run().into(),
         }
     }
 }
@@ -135,7 +218,7 @@ impl SearchPattern {
         let (is_matched, calculated_weight) = match self {
             SearchPattern::Regex(re) => {
                 let b = re.is_match(text);
-                (b, 1.0)
+                (b, REGEX_WEIGHT)
             }
             SearchPattern::Substring(s) => {
                 let b = text.contains(s);
@@ -145,6 +228,26 @@ impl SearchPattern {
                 let b = text == s;
                 (b, 1024.0)
             }
+            SearchPattern::Glob(g) => {
+                let b = glob_match(g, text);
+                // Wildcards do not count towards the specificity. A glob ranks above a regex
+                // and below a predicate match, even if it consists only of wildcards. It ranks
+                // below a substring search for the same literals, e.g. `**a*` below `%a`.
+                let literals = g.chars().filter(|c| !matches!(c, '*' | '?')).count();
+                let ratio = literals as f64 / text.chars().count().max(1) as f64;
+                (b, 2.0 * REGEX_WEIGHT + 509.0 * ratio)
+            }
+            SearchPattern::CaseInsensitive(p) => {
+                // Ignoring the case halves the weight, but never drops below a regex match.
+                let (b, weight) = p.matches(&text.to_lowercase());
+                (b, (weight as f64 / 2000.0).max(REGEX_WEIGHT))
+            }
+            SearchPattern::Compare(cmp, value) => {
+                let b = text.parse::<i64>().is_ok_and(|n| cmp.holds(n, *value));
+                (b, PREDICATE_WEIGHT)
+            }
+            // Modes are not text, see [`SearchPattern::matches_modes`]
+            SearchPattern::Mode(_) => (false, 0.0),
         };
         if !is_matched {
             return (false, 0);
@@ -154,6 +257,88 @@ impl SearchPattern {
         let weight = (calculated_weight * 1000.0).trunc() as u64;
         (is_matched, weight)
     }
+
+    /// Checks if any of the provided modes satisfies the contained [`Mode`] pattern.
+    ///
+    /// Refresh rates are allowed to deviate by up to [`MAX_RR_DEVIATION`] mHz.
+    pub fn matches_modes(&self, modes: &VecDeque<WlMode>) -> (bool, u64) {
+        let SearchPattern::Mode(mode) = self else {
+            return (false, 0);
+        };
+        let b = modes.iter().any(|m| match *mode {
+            Mode::WiHe(w, h) => m.width() == w && m.height() == h,
+            Mode::WiHeRe(w, h, r) => {
+                m.width() == w
+                    && m.height() == h
+                    && r.abs_diff(m.refresh()) as i32 <= MAX_RR_DEVIATION
+            }
+            _ => false,
+        });
+        if !b {
+            return (false, 0);
+        }
+        (b, (PREDICATE_WEIGHT * 1000.0) as u64)
+    }
+}
+
+impl SearchPattern {
+    /// Turns a full text, substring or glob pattern into a case-insensitive one.
+    pub fn case_insensitive(self) -> Self {
+        match self {
+            SearchPattern::Substring(s) => {
+                Self::CaseInsensitive(Box::new(Self::Substring(s.to_lowercase())))
+            }
+            SearchPattern::Fulltext(s) => {
+                Self::CaseInsensitive(Box::new(Self::Fulltext(s.to_lowercase())))
+            }
+            SearchPattern::Glob(s) => Self::CaseInsensitive(Box::new(Self::Glob(s.to_lowercase()))),
+            other => other,
+        }
+    }
+}
+
+/// Matches `text` against a glob `pattern`.
+///
+/// `*` matches any sequence of characters (including none), `?` matches exactly one character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position of the last '*' in the pattern and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // let the last '*' consume one more character
+                Some((star, star_t)) => {
+                    backtrack = Some((star, star_t + 1));
+                    p = star + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl Comparison {
+    fn holds(&self, lhs: i64, rhs: i64) -> bool {
+        match self {
+            Comparison::Less => lhs < rhs,
+            Comparison::LessEqual => lhs <= rhs,
+            Comparison::Greater => lhs > rhs,
+            Comparison::GreaterEqual => lhs >= rhs,
+        }
+    }
 }
 
 impl SearchKind {
@@ -162,6 +347,9 @@ impl SearchKind {
             SearchKind::Regex => '/',
             SearchKind::Substring => '%',
             SearchKind::Fulltext => '=',
+            SearchKind::Glob => '*',
+            SearchKind::Less => '<',
+            SearchKind::Greater => '>',
         }
     }
     pub fn as_str(&self) -> &'static str {
@@ -169,24 +357,41 @@ impl SearchKind {
             SearchKind::Regex => "Regex",
             SearchKind::Substring => "Substring",
             SearchKind::Fulltext => "Fulltext",
+            SearchKind::Glob => "Glob",
+            SearchKind::Less => "Less",
+            SearchKind::Greater => "Greater",
         }
     }
+    pub fn allows_case_insensitive(&self) -> bool {
+        matches!(
+            self,
+            SearchKind::Fulltext | SearchKind::Substring | SearchKind::Glob
+        )
+    }
     pub fn from_char(c: char) -> Option<Self> {
         match c {
             '/' => Some(SearchKind::Regex),
             '%' => Some(SearchKind::Substring),
             '=' => Some(SearchKind::Fulltext),
+            '*' => Some(SearchKind::Glob),
+            '<' => Some(SearchKind::Less),
+            '>' => Some(SearchKind::Greater),
             _ => None,
         }
     }
 }
 
-impl SearchPattern {
-    fn as_str(&self) -> &str {
+impl Display for SearchPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            SearchPattern::Regex(re) => re.as_str(),
-            SearchPattern::Substring(s) => s,
-            SearchPattern::Fulltext(s) => s,
+            SearchPattern::Regex(re) => write!(f, "{}", re.as_str()),
+            SearchPattern::Substring(s) => write!(f, "{s}"),
+            SearchPattern::Fulltext(s) => write!(f, "{s}"),
+            SearchPattern::Glob(s) => write!(f, "{s}"),
+            SearchPattern::CaseInsensitive(p) => p.fmt(f),
+            SearchPattern::Compare(Comparison::Less | Comparison::Greater, n) => write!(f, "{n}"),
+            SearchPattern::Compare(_, n) => write!(f, "={n}"),
+            SearchPattern::Mode(m) => write!(f, "{}", m.to_short_hz_string()),
         }
     }
 }
@@ -197,6 +402,10 @@ impl PartialEq for SearchPattern {
             (SearchPattern::Regex(s), SearchPattern::Regex(o)) => s.as_str() == o.as_str(),
             (SearchPattern::Substring(s), SearchPattern::Substring(o)) => s == o,
             (SearchPattern::Fulltext(s), SearchPattern::Fulltext(o)) => s == o,
+            (SearchPattern::Glob(s), SearchPattern::Glob(o)) => s == o,
+            (SearchPattern::CaseInsensitive(s), SearchPattern::CaseInsensitive(o)) => s == o,
+            (SearchPattern::Compare(sc, s), SearchPattern::Compare(oc, o)) => sc == oc && s == o,
+            (SearchPattern::Mode(s), SearchPattern::Mode(o)) => s == o,
             (_, _) => false,
         }
     }
@@ -220,6 +429,12 @@ impl From<MultiSearchResult> for SearchResult {
     }
 }
 
+impl From<AnySearchResult> for SearchResult {
+    fn from(q: AnySearchResult) -> Self {
+        Self::Any(q)
+    }
+}
+
 impl From<SingleSearch> for Search {
     fn from(q: SingleSearch) -> Self {
         Self::Single(q)
@@ -232,6 +447,12 @@ impl From<MultiSearch> for Search {
     }
 }
 
+impl From<AnySearch> for Search {
+    fn from(q: AnySearch) -> Self {
+        Self::Any(q)
+    }
+}
+
 impl<'a> From<SingleQuery<'a>> for Query<'a> {
     fn from(q: SingleQuery<'a>) -> Self {
         Self::Single(q)
@@ -244,11 +465,18 @@ impl<'a> From<MultiQuery<'a>> for Query<'a> {
     }
 }
 
+impl<'a> From<AnyQuery<'a>> for Query<'a> {
+    fn from(q: AnyQuery<'a>) -> Self {
+        Self::Any(q)
+    }
+}
+
 impl Display for Search {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Search::Single(s) => s.fmt(f),
             Search::Multi(s) => s.fmt(f),
+            Search::Any(s) => s.fmt(f),
         }
     }
 }
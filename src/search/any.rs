@@ -0,0 +1,197 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::profile::PhysicalSize;
+use crate::wl_backend::{Edid, WlMode};
+
+use super::{MultiQuery, MultiSearch, SingleSearch, SingleSearchResult};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnySearchResult {
+    alternatives: Vec<SingleSearchResult>,
+}
+
+/// A list of alternative [`SingleSearch`]es of which at least one has to match.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnySearch {
+    #[serde(deserialize_with = "deserialize_alternatives")]
+    any: Vec<SingleSearch>,
+}
+
+/// An empty list of alternatives could never match, so it is rejected.
+fn deserialize_alternatives<'de, D>(deserializer: D) -> Result<Vec<SingleSearch>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let any = Vec::<SingleSearch>::deserialize(deserializer)?;
+    if any.is_empty() {
+        return Err(serde::de::Error::invalid_length(0, &"at least one search"));
+    }
+    Ok(any)
+}
+
+/// Runs every alternative like a [`MultiQuery`] but combines the results differently.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnyQuery<'a> {
+    query: MultiQuery<'a>,
+}
+
+impl AnySearchResult {
+    /// Returns `true` if at least one inner [`SingleSearchResult::is_ok()`] returns `true`.
+    pub fn is_ok(&self) -> bool {
+        self.alternatives.iter().any(|ssr| ssr.is_ok())
+    }
+
+    /// Returns the highest [`SingleSearchResult::specificity()`] of all matching alternatives.
+    pub fn specificity(&self) -> u64 {
+        self.alternatives
+            .iter()
+            .filter(|ssr| ssr.is_ok())
+            .map(|ssr| ssr.specificity())
+            .max()
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn results(&self) -> &[SingleSearchResult] {
+        &self.alternatives
+    }
+}
+
+impl AnySearch {
+    pub fn new(any: Vec<SingleSearch>) -> Self {
+        Self { any }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &SingleSearch> {
+        self.any.iter()
+    }
+    pub fn query<'a>(self) -> AnyQuery<'a> {
+        AnyQuery::new(self)
+    }
+}
+
+impl<'a> AnyQuery<'a> {
+    pub fn new(search: AnySearch) -> Self {
+        Self {
+            query: MultiSearch::new(search.any).query(),
+        }
+    }
+    pub fn description(mut self, description: &'a str) -> Self {
+        self.query = self.query.description(description);
+        self
+    }
+    pub fn model(mut self, model: &'a str) -> Self {
+        self.query = self.query.model(model);
+        self
+    }
+    pub fn name(mut self, name: &'a str) -> Self {
+        self.query = self.query.name(name);
+        self
+    }
+    pub fn serial(mut self, serial: &'a str) -> Self {
+        self.query = self.query.serial(serial);
+        self
+    }
+    pub fn vendor(mut self, vendor: &'a str) -> Self {
+        self.query = self.query.vendor(vendor);
+        self
+    }
+    pub fn edid(mut self, edid: Option<&'a Edid>) -> Self {
+        self.query = self.query.edid(edid);
+        self
+    }
+    pub fn size(mut self, size: PhysicalSize) -> Self {
+        self.query = self.query.size(size);
+        self
+    }
+    pub fn modes(mut self, modes: &'a VecDeque<WlMode>) -> Self {
+        self.query = self.query.modes(modes);
+        self
+    }
+    pub fn run(self) -> AnySearchResult {
+        AnySearchResult {
+            alternatives: self.query.run().into_results(),
+        }
+    }
+}
+
+/// Formats the search as TOML like it is written in the config.
+impl Display for AnySearch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{ any = [")?;
+        for (i, search) in self.any.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            // quoted and escaped like the serializer does
+            toml::Value::String(search.to_string()).fmt(f)?;
+        }
+        write!(f, "] }}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use crate::search::{Search, SingleSearch};
+
+    use super::AnySearch;
+
+    fn any(searches: &[&str]) -> AnySearch {
+        AnySearch::new(
+            searches
+                .iter()
+                .map(|s| SingleSearch::from_str(s).unwrap())
+                .collect(),
+        )
+    }
+
+    #[rstest]
+    #[case(&["s=ABC", "s=DEF"], "DEF", true)]
+    #[case(&["s=ABC", "s=DEF"], "GHI", false)]
+    #[case(&["s=ABC", "s=DEF", "s=GHI", "s=JKL", "s=MNO", "s=PQR"], "PQR", true)]
+    #[case(&[], "ABC", false)]
+    fn any_search(#[case] searches: &[&str], #[case] serial: &str, #[case] ok: bool) {
+        let asr = any(searches).query().serial(serial).run();
+        assert_eq!(asr.is_ok(), ok);
+    }
+
+    #[test]
+    fn any_search_specificity() {
+        let best = any(&["s=ABC"]).query().serial("ABC").run().specificity();
+        let asr = any(&["s/A", "s=ABC", "s=DEF"]).query().serial("ABC").run();
+        assert_eq!(asr.specificity(), best);
+    }
+
+    #[test]
+    fn deserialize_any_search() {
+        #[derive(serde::Deserialize)]
+        struct Output {
+            search: Search,
+        }
+        let output: Output = toml::from_str(r#"search = { any = ["s=ABC", "s=DEF"] }"#).unwrap();
+        assert_eq!(output.search, Search::Any(any(&["s=ABC", "s=DEF"])));
+        assert_eq!(output.search.to_string(), r#"{ any = ["s=ABC", "s=DEF"] }"#);
+        assert!(toml::from_str::<Output>(r#"search = { all = ["s=ABC"] }"#).is_err());
+        assert!(toml::from_str::<Output>(r#"search = { any = [] }"#).is_err());
+    }
+
+    #[rstest]
+    #[case(&["s=ABC", "s=DEF"])]
+    #[case(&["n/DP-\\d", "!m~*dell*"])]
+    #[case(&["d%\"quoted\" name", "v=it's"])]
+    fn display_any_search_as_toml(#[case] searches: &[&str]) {
+        #[derive(serde::Deserialize)]
+        struct Output {
+            search: Search,
+        }
+        let search = Search::Any(any(searches));
+        let output: Output = toml::from_str(&format!("search = {search}")).unwrap();
+        assert_eq!(output.search, search);
+    }
+}
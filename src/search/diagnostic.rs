@@ -0,0 +1,353 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use super::single::NEGATION_PREFIX;
+use super::{
+    CompareMethod, ParseSingleSearchError, SearchField, SearchKind, SearchPattern, SingleSearch,
+    CASE_INSENSITIVE_MODIFIER,
+};
+use crate::error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A message about a search string pointing at the position it refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchDiagnostic {
+    pub severity: Severity,
+    pub input: String,
+    /// Position of the annotated part in characters
+    pub position: usize,
+    /// Length of the annotated part in characters, at least 1
+    pub len: usize,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+/// Attributes and search kinds known by earlier versions, which searched every other string as
+/// full text.
+const LEGACY_ATTRS: &str = "dmnsv";
+const LEGACY_KINDS: &str = "/%=";
+
+/// Where the parts of a search string start, in characters.
+///
+/// Mirrors the way [`SingleSearch::from_str`] splits a search string.
+#[derive(Debug, Default)]
+struct Layout {
+    /// Length of the negation prefixes
+    offset: usize,
+    /// Position of the first attribute that is not part of the attribute list
+    attrs_end: usize,
+    /// Position of the first attribute that occurs twice
+    duplicate: Option<usize>,
+    /// Position of the search kind
+    kind: Option<usize>,
+}
+
+impl SearchDiagnostic {
+    fn new(severity: Severity, input: &str, position: usize, message: String) -> Self {
+        Self {
+            severity,
+            input: input.to_string(),
+            position,
+            len: 1,
+            message,
+            suggestion: None,
+        }
+    }
+    fn len(mut self, len: usize) -> Self {
+        self.len = len.max(1);
+        self
+    }
+    fn suggestion(mut self, suggestion: String) -> Self {
+        self.suggestion = Some(suggestion);
+        self
+    }
+
+    /// Annotates a [`ParseSingleSearchError`] with the position it was caused at.
+    pub fn from_error(input: &str, err: &ParseSingleSearchError) -> Self {
+        let layout = Layout::new(input);
+        let len = input.chars().count();
+        let pattern_start = layout.kind.map(|k| k + 1).unwrap_or(layout.offset);
+        let (position, annotated) = match err {
+            ParseSingleSearchError::FieldSet { .. } => {
+                (layout.duplicate.unwrap_or(layout.attrs_end), 1)
+            }
+            ParseSingleSearchError::DoubleNegation => (1, 1),
+            ParseSingleSearchError::MissingAttributes
+            | ParseSingleSearchError::CaseInsensitiveKind { .. }
+            | ParseSingleSearchError::ModeKind { .. } => (layout.kind.unwrap_or(layout.offset), 1),
+            ParseSingleSearchError::Regex { .. }
+            | ParseSingleSearchError::Number { .. }
+            | ParseSingleSearchError::ParseMode { .. }
+            | ParseSingleSearchError::InvalidMode { .. } => {
+                (pattern_start, len.saturating_sub(pattern_start))
+            }
+        };
+        let message = match err {
+            // The regex error already contains its own caret annotation, keep only the reason.
+            ParseSingleSearchError::Regex { source } => {
+                let reason = source.to_string();
+                let reason = reason.lines().last().unwrap_or_default();
+                format!("{err}: {}", reason.trim_start_matches("error: "))
+            }
+            _ => error::report(err),
+        };
+        Self::new(Severity::Error, input, position, message).len(annotated)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Layout {
+    fn new(input: &str) -> Self {
+        let mut layout = Self::default();
+        let mut chars = input.chars().peekable();
+        while chars.next_if_eq(&NEGATION_PREFIX).is_some() {
+            layout.offset += 1;
+        }
+        let body: String = chars.clone().collect();
+        if let Some(rest) = body.strip_prefix(SearchField::MODE_ATTR) {
+            let attrs_end = layout.offset + SearchField::MODE_ATTR.len();
+            layout.attrs_end = attrs_end;
+            if rest
+                .chars()
+                .next()
+                .and_then(SearchKind::from_char)
+                .is_some()
+            {
+                layout.kind = Some(attrs_end);
+                return layout;
+            }
+        }
+
+        let mut seen = vec![];
+        let mut position = layout.offset;
+        let mut in_attrs = true;
+        for c in chars {
+            match SearchField::from_char(c) {
+                Some(field) if in_attrs => {
+                    if seen.contains(&field) && layout.duplicate.is_none() {
+                        layout.duplicate = Some(position);
+                    }
+                    seen.push(field);
+                }
+                _ if c == CASE_INSENSITIVE_MODIFIER && in_attrs => {
+                    layout.attrs_end = position;
+                    in_attrs = false;
+                }
+                _ if SearchKind::from_char(c).is_some() => {
+                    if in_attrs {
+                        layout.attrs_end = position;
+                    }
+                    layout.kind = Some(position);
+                    return layout;
+                }
+                _ => break,
+            }
+            position += 1;
+        }
+        if in_attrs {
+            layout.attrs_end = position;
+        }
+        layout
+    }
+}
+
+/// Returns true if earlier versions searched the whole string as full text.
+fn is_legacy_full_text(input: &str) -> bool {
+    let rest = input.trim_start_matches(|c| LEGACY_ATTRS.contains(c));
+    !rest.starts_with(|c| LEGACY_KINDS.contains(c))
+}
+
+/// Checks a search string and returns all problems found.
+///
+/// Errors describe why the string cannot be parsed. Warnings point out strings that can be
+/// parsed but probably do not do what was intended, like an attribute list without a search
+/// kind (e.g. `vsDP-1`). The whole string is then searched as full text. Strings that earlier
+/// versions searched as full text but are parsed differently now (e.g. `*Dell*`) are warned
+/// about, too.
+pub fn diagnose(input: &str) -> Vec<SearchDiagnostic> {
+    // the full text search kind keeps the meaning of earlier versions
+    let legacy_suggestion = format!("{}{input}", SearchKind::Fulltext.as_char());
+    let search = match SingleSearch::from_str(input) {
+        Ok(search) => search,
+        Err(err) => {
+            let diagnostic = SearchDiagnostic::from_error(input, &err);
+            return match is_legacy_full_text(input) {
+                true => vec![diagnostic.suggestion(legacy_suggestion)],
+                false => vec![diagnostic],
+            };
+        }
+    };
+
+    let mut diagnostics = vec![];
+    let full_text = SearchPattern::Fulltext(input.to_string());
+    let unchanged = !search.negated
+        && search.method == CompareMethod::AtleastOne
+        && search.pattern == full_text;
+    if is_legacy_full_text(input) && !unchanged {
+        let message = "earlier versions searched the whole string as full text, \
+            now it is parsed as a search with new syntax"
+            .to_string();
+        let len = input.chars().count();
+        diagnostics.push(
+            SearchDiagnostic::new(Severity::Warning, input, 0, message)
+                .len(len)
+                .suggestion(legacy_suggestion),
+        );
+    }
+    let layout = Layout::new(input);
+    let attrs = layout.attrs_end - layout.offset;
+    let next = input.chars().nth(layout.attrs_end);
+    // Single letters and lowercase words are too common at the start of a full text search
+    // (e.g. eDP-1, dell) to warn about.
+    let looks_like_attrs = attrs >= 2 && next.is_some_and(|c| !c.is_lowercase());
+    if layout.kind.is_none() && search.kind == SearchKind::Fulltext && looks_like_attrs {
+        let (head, tail) = input.split_at(
+            input
+                .char_indices()
+                .nth(layout.attrs_end)
+                .map(|(i, _)| i)
+                .unwrap_or(input.len()),
+        );
+        let message = "attribute list ends here, but no search kind follows; \
+            the whole string is searched as full text"
+            .to_string();
+        let suggestion = format!("{head}{}{tail}", SearchKind::Fulltext.as_char());
+        diagnostics.push(
+            SearchDiagnostic::new(Severity::Warning, input, layout.attrs_end, message)
+                .suggestion(suggestion),
+        );
+    }
+    diagnostics
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl Display for SearchDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}: {}", self.severity, self.message)?;
+        writeln!(f, "  | {}", self.input)?;
+        write!(
+            f,
+            "  | {}{}",
+            " ".repeat(self.position),
+            "^".repeat(self.len)
+        )?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n  = did you mean {suggestion:?}?")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("vsDP-1", 2, Some("vs=DP-1"))]
+    #[case("ms1234", 2, Some("ms=1234"))]
+    #[case("!vsDP-1", 3, Some("!vs=DP-1"))]
+    fn warn_about_missing_kind(
+        #[case] input: &str,
+        #[case] position: usize,
+        #[case] suggestion: Option<&str>,
+    ) {
+        let diagnostics = diagnose(input);
+        // the negation of the last case changed the meaning as well
+        let diagnostic = diagnostics.last().unwrap();
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(diagnostic.position, position);
+        assert_eq!(diagnostic.suggestion.as_deref(), suggestion);
+    }
+
+    #[rstest]
+    #[case("eDP-1")]
+    #[case("DP-1")]
+    #[case("dell")]
+    #[case("vs=DP-1")]
+    #[case("~dell")]
+    #[case("=v~=dell")]
+    #[case("=has_mode=3840x2160")]
+    fn no_diagnostics(#[case] input: &str) {
+        assert_eq!(diagnose(input), vec![]);
+    }
+
+    #[rstest]
+    #[case("*Dell*")]
+    #[case("m*U27??")]
+    #[case("v~=dell")]
+    #[case("~%dell")]
+    #[case("!eDP-1")]
+    #[case("w>600")]
+    #[case("e=ab12")]
+    #[case("has_mode=3840x2160")]
+    fn warn_about_changed_meaning(#[case] input: &str) {
+        let diagnostics = diagnose(input);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(
+            (diagnostics[0].position, diagnostics[0].len),
+            (0, input.len())
+        );
+        let suggestion = format!("={input}");
+        assert_eq!(diagnostics[0].suggestion.as_ref(), Some(&suggestion));
+        // the suggestion keeps the meaning of earlier versions
+        assert_eq!(diagnose(&suggestion), vec![]);
+    }
+
+    #[rstest]
+    #[case("<600", Some("=<600"))]
+    #[case("v~/dell", Some("=v~/dell"))]
+    #[case("v/DP-[", None)]
+    fn suggest_legacy_full_text_for_errors(#[case] input: &str, #[case] suggestion: Option<&str>) {
+        let diagnostics = diagnose(input);
+        assert!(diagnostics[0].is_error());
+        assert_eq!(diagnostics[0].suggestion.as_deref(), suggestion);
+    }
+
+    #[rstest]
+    #[case("vsv=DP-1", 2, 1)]
+    #[case("v/DP-[", 2, 4)]
+    #[case("w>=abc", 2, 4)]
+    #[case("<600", 0, 1)]
+    #[case("v~/dell", 2, 1)]
+    #[case("!!DP-1", 1, 1)]
+    #[case("has_mode=best", 9, 4)]
+    #[case("has_mode%3840x2160", 8, 1)]
+    fn locate_errors(#[case] input: &str, #[case] position: usize, #[case] len: usize) {
+        let diagnostics = diagnose(input);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+        assert_eq!(
+            (diagnostics[0].position, diagnostics[0].len),
+            (position, len)
+        );
+    }
+
+    #[test]
+    fn display_caret() {
+        let diagnostic = &diagnose("vsDP-1")[0];
+        let s = diagnostic.to_string();
+        let lines: Vec<_> = s.lines().collect();
+        assert_eq!(lines[1], "  | vsDP-1");
+        assert_eq!(lines[2], "  |   ^");
+        assert_eq!(lines[3], "  = did you mean \"vs=DP-1\"?");
+    }
+}
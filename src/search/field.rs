@@ -15,10 +15,16 @@ pub enum SearchField {
     Vendor,
     Model,
     Serial,
+    EdidProduct,
+    EdidYear,
+    EdidHash,
+    Width,
+    Height,
+    Mode,
 }
 
 impl FieldSet {
-    pub const N: usize = 5;
+    pub const N: usize = 11;
 
     pub fn new(field: SearchField) -> Self {
         let mut set: [_; Self::N] = Default::default();
@@ -88,13 +94,24 @@ impl FieldSet {
 }
 
 impl SearchField {
-    pub fn as_char(&self) -> char {
+    /// The [`SearchField::Mode`] attribute is a keyword instead of a single letter and cannot be
+    /// combined with other attributes.
+    pub const MODE_ATTR: &'static str = "has_mode";
+
+    /// Returns the attribute as it is written in a search.
+    pub fn as_attr(&self) -> &'static str {
         match self {
-            SearchField::Description => 'd',
-            SearchField::Model => 'm',
-            SearchField::Name => 'n',
-            SearchField::Serial => 's',
-            SearchField::Vendor => 'v',
+            SearchField::Description => "d",
+            SearchField::Model => "m",
+            SearchField::Name => "n",
+            SearchField::Serial => "s",
+            SearchField::Vendor => "v",
+            SearchField::EdidProduct => "p",
+            SearchField::EdidYear => "y",
+            SearchField::EdidHash => "e",
+            SearchField::Width => "w",
+            SearchField::Height => "h",
+            SearchField::Mode => Self::MODE_ATTR,
         }
     }
     pub fn as_str(&self) -> &'static str {
@@ -104,6 +121,12 @@ impl SearchField {
             SearchField::Name => "Name",
             SearchField::Serial => "Serial",
             SearchField::Vendor => "Vendor",
+            SearchField::EdidProduct => "EDID product code",
+            SearchField::EdidYear => "EDID manufacture year",
+            SearchField::EdidHash => "EDID hash",
+            SearchField::Width => "Width",
+            SearchField::Height => "Height",
+            SearchField::Mode => "Supported mode",
         }
     }
     pub fn from_char(c: char) -> Option<Self> {
@@ -113,6 +136,11 @@ impl SearchField {
             'n' => Some(SearchField::Name),
             's' => Some(SearchField::Serial),
             'v' => Some(SearchField::Vendor),
+            'p' => Some(SearchField::EdidProduct),
+            'y' => Some(SearchField::EdidYear),
+            'e' => Some(SearchField::EdidHash),
+            'w' => Some(SearchField::Width),
+            'h' => Some(SearchField::Height),
             _ => None,
         }
     }
@@ -169,7 +197,7 @@ impl Display for SearchField {
 impl Display for FieldSet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for field in self.set.into_iter().flatten() {
-            write!(f, "{}", field.as_char())?;
+            write!(f, "{}", field.as_attr())?;
         }
         Ok(())
     }
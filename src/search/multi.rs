@@ -2,6 +2,11 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
+use std::collections::VecDeque;
+
+use crate::profile::PhysicalSize;
+use crate::wl_backend::{Edid, WlMode};
+
 use super::{SingleSearch, SingleSearchResult};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -23,6 +28,9 @@ pub struct MultiQuery<'a> {
     name: &'a str,
     serial: &'a str,
     vendor: &'a str,
+    edid: Option<&'a Edid>,
+    size: PhysicalSize,
+    modes: Option<&'a VecDeque<WlMode>>,
 }
 
 impl MultiSearchResult {
@@ -35,6 +43,14 @@ impl MultiSearchResult {
     pub fn specificity(&self) -> u64 {
         self.searches.iter().map(|ssr| ssr.specificity()).sum()
     }
+
+    pub(crate) fn results(&self) -> &[SingleSearchResult] {
+        &self.searches
+    }
+
+    pub(crate) fn into_results(self) -> Vec<SingleSearchResult> {
+        self.searches
+    }
 }
 
 impl MultiSearch {
@@ -58,6 +74,9 @@ impl<'a> MultiQuery<'a> {
             name: Default::default(),
             serial: Default::default(),
             vendor: Default::default(),
+            edid: Default::default(),
+            size: Default::default(),
+            modes: Default::default(),
         }
     }
     pub fn description(mut self, description: &'a str) -> Self {
@@ -80,18 +99,34 @@ impl<'a> MultiQuery<'a> {
         self.vendor = vendor;
         self
     }
+    pub fn edid(mut self, edid: Option<&'a Edid>) -> Self {
+        self.edid = edid;
+        self
+    }
+    pub fn size(mut self, size: PhysicalSize) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn modes(mut self, modes: &'a VecDeque<WlMode>) -> Self {
+        self.modes = Some(modes);
+        self
+    }
     pub fn run(self) -> MultiSearchResult {
         let mut ssrs: Vec<SingleSearchResult> = vec![];
         for ss in self.search.searches {
-            let ssr = ss
+            let mut query = ss
                 .query()
                 .description(self.description)
                 .model(self.model)
                 .name(self.name)
                 .serial(self.serial)
                 .vendor(self.vendor)
-                .run();
-            ssrs.push(ssr);
+                .edid(self.edid)
+                .size(self.size);
+            if let Some(modes) = self.modes {
+                query = query.modes(modes);
+            }
+            ssrs.push(query.run());
         }
         MultiSearchResult { searches: ssrs }
     }
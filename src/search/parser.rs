@@ -1,36 +1,84 @@
+use std::num::ParseIntError;
 use std::str::FromStr;
 
 use itertools::Itertools;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use snafu::prelude::*;
 
+use crate::profile::{Mode, ParseModeError};
+
+use super::single::NEGATION_PREFIX;
 use super::{
-    CompareMethod, FieldSet, FieldSetError, SearchField, SearchKind, SearchPattern, SingleSearch,
+    CompareMethod, Comparison, FieldSet, FieldSetError, SearchDiagnostic, SearchField, SearchKind,
+    SearchPattern, SingleSearch, CASE_INSENSITIVE_MODIFIER,
 };
 
 #[derive(Debug, PartialEq, Snafu)]
 #[snafu(context(suffix(Ctx)))]
 pub enum ParseSingleSearchError {
+    #[snafu(display("Invalid attribute list"))]
     FieldSet { source: FieldSetError },
+    #[snafu(display("Invalid regex"))]
     Regex { source: regex::Error },
+    #[snafu(display("Cannot parse {pattern:?} as a number"))]
+    Number {
+        source: ParseIntError,
+        pattern: String,
+    },
+    #[snafu(display(
+        "Search kind {} cannot be combined with '{CASE_INSENSITIVE_MODIFIER}'",
+        kind.as_str()
+    ))]
+    CaseInsensitiveKind { kind: SearchKind },
+    #[snafu(display("A search cannot be negated twice"))]
+    DoubleNegation,
+    #[snafu(display("A comparison needs at least one attribute"))]
+    MissingAttributes,
+    #[snafu(display("Cannot parse {pattern:?} as mode"))]
+    ParseMode {
+        source: ParseModeError,
+        pattern: String,
+    },
+    #[snafu(display("{attr} expects a mode like 3840x2160 or 3840x2160@60Hz, got {pattern:?}"))]
+    InvalidMode { attr: &'static str, pattern: String },
+    #[snafu(display("{attr} only supports the search kind '{}'", SearchKind::Fulltext.as_char()))]
+    ModeKind { attr: &'static str },
 }
 
 impl FromStr for SingleSearch {
     type Err = ParseSingleSearchError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(rest) = s.strip_prefix(NEGATION_PREFIX) {
+            ensure!(!rest.starts_with(NEGATION_PREFIX), DoubleNegationCtx);
+            return Ok(SingleSearch::from_str(rest)?.negate());
+        }
+        if let Some(rest) = s.strip_prefix(SearchField::MODE_ATTR) {
+            if let Some(kind) = rest.chars().next().and_then(SearchKind::from_char) {
+                return parse_mode_search(kind, &rest[1..]);
+            }
+        }
+
         let original_str = s.to_string();
         let mut chars = s.chars().peekable();
         let mut fieldset = FieldSet::default();
+        let mut case_insensitive = false;
         let kind = loop {
             if let Some((c, sf)) = chars
                 .peeking_take_while(|c| {
-                    SearchField::from_char(*c).is_some() || SearchKind::from_char(*c).is_some()
+                    SearchField::from_char(*c).is_some()
+                        || SearchKind::from_char(*c).is_some()
+                        || (*c == CASE_INSENSITIVE_MODIFIER && !case_insensitive)
                 })
                 .next()
                 .map(|c| (c, SearchField::from_char(c)))
             {
-                if let Some(sf) = sf {
+                if c == CASE_INSENSITIVE_MODIFIER {
+                    case_insensitive = true;
+                } else if case_insensitive && sf.is_some() {
+                    // the modifier has to be followed by the search kind
+                    break None;
+                } else if let Some(sf) = sf {
                     fieldset.try_insert(sf).context(FieldSetCtx)?;
                 } else if let Some(kind) = SearchKind::from_char(c) {
                     break Some(kind);
@@ -47,6 +95,9 @@ impl FromStr for SingleSearch {
         let method;
         if let Some(k) = kind {
             search_kind = k;
+            if matches!(k, SearchKind::Less | SearchKind::Greater) && fieldset.is_empty() {
+                return MissingAttributesCtx.fail();
+            }
             if fieldset.is_empty() {
                 fieldset.fill_default();
                 method = CompareMethod::AtleastOne;
@@ -66,11 +117,56 @@ impl FromStr for SingleSearch {
             SearchKind::Regex => SearchPattern::Regex(regex::Regex::new(&s).context(RegexCtx)?),
             SearchKind::Substring => SearchPattern::Substring(s),
             SearchKind::Fulltext => SearchPattern::Fulltext(s),
+            SearchKind::Glob => SearchPattern::Glob(s),
+            SearchKind::Less | SearchKind::Greater => parse_comparison(search_kind, &s)?,
+        };
+        let sp = if case_insensitive && kind.is_some() {
+            let kind = search_kind;
+            ensure!(
+                kind.allows_case_insensitive(),
+                CaseInsensitiveKindCtx { kind }
+            );
+            sp.case_insensitive()
+        } else {
+            sp
         };
         Ok(SingleSearch::new(fieldset, search_kind, sp, method))
     }
 }
 
+/// Parses the pattern of a comparison. A leading `=` makes the comparison inclusive.
+fn parse_comparison(kind: SearchKind, s: &str) -> Result<SearchPattern, ParseSingleSearchError> {
+    let (inclusive, number) = match s.strip_prefix('=') {
+        Some(number) => (true, number),
+        None => (false, s),
+    };
+    let value = number.parse().context(NumberCtx { pattern: s })?;
+    let cmp = match (kind, inclusive) {
+        (SearchKind::Less, false) => Comparison::Less,
+        (SearchKind::Less, true) => Comparison::LessEqual,
+        (SearchKind::Greater, false) => Comparison::Greater,
+        _ => Comparison::GreaterEqual,
+    };
+    Ok(SearchPattern::Compare(cmp, value))
+}
+
+/// Parses the pattern following the `has_mode` keyword.
+fn parse_mode_search(kind: SearchKind, s: &str) -> Result<SingleSearch, ParseSingleSearchError> {
+    let attr = SearchField::MODE_ATTR;
+    ensure!(kind == SearchKind::Fulltext, ModeKindCtx { attr });
+    let mode = Mode::from_str(s).context(ParseModeCtx { pattern: s })?;
+    ensure!(
+        matches!(mode, Mode::WiHe(_, _) | Mode::WiHeRe(_, _, _)),
+        InvalidModeCtx { attr, pattern: s }
+    );
+    Ok(SingleSearch::new(
+        FieldSet::new(SearchField::Mode),
+        kind,
+        SearchPattern::Mode(mode),
+        CompareMethod::Exact,
+    ))
+}
+
 impl Serialize for SingleSearch {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -87,7 +183,8 @@ impl<'de> Deserialize<'de> for SingleSearch {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        let ssearch = SingleSearch::from_str(&s).map_err(serde::de::Error::custom)?;
+        let ssearch = SingleSearch::from_str(&s)
+            .map_err(|err| serde::de::Error::custom(SearchDiagnostic::from_error(&s, &err)))?;
         Ok(ssearch)
     }
 }
@@ -100,13 +197,21 @@ mod tests {
 
     use crate::search::SearchField as SF;
     use crate::search::SearchKind as SK;
-    use crate::search::SingleSearch;
+    use crate::search::{CompareMethod, FieldSet, SearchPattern, SingleSearch};
     use SF::Description as D;
+    use SF::EdidHash as E;
+    use SF::EdidProduct as P;
+    use SF::EdidYear as Y;
+    use SF::Height as H;
     use SF::Model as M;
     use SF::Name as N;
     use SF::Serial as S;
     use SF::Vendor as V;
+    use SF::Width as W;
     use SK::Fulltext as Ft;
+    use SK::Glob as Gl;
+    use SK::Greater as Gt;
+    use SK::Less as Lt;
     use SK::Regex as Rx;
     use SK::Substring as Sstr;
 
@@ -128,6 +233,25 @@ mod tests {
     #[case("m/=%model", [M], Rx, 7)]
     #[case("DP-1", [D,N,V,M,S], Ft, 4)]
     #[case("vsDP-1", [D,N,V,M,S], Ft, 6)]
+    #[case("p=1234", [P], Ft, 4)]
+    #[case("yp=2023", [Y,P], Ft, 4)]
+    #[case("e%ab12", [E], Sstr, 4)]
+    #[case("w>=600", [W], Gt, 4)] // comparisons
+    #[case("wh<500", [W,H], Lt, 3)]
+    #[case("h>-1", [H], Gt, 2)]
+    #[case("has_mode=3840x2160", [SF::Mode], Ft, 9)]
+    #[case("has_mode=3840x2160@60Hz", [SF::Mode], Ft, 14)]
+    #[case("has_mode", [D,N,V,M,S], Ft, 8)]
+    #[case("**Dell*", [D,N,V,M,S], Gl, 6)] // glob
+    #[case("m*U27??", [M], Gl, 5)]
+    #[case("v~=dell", [V], Ft, 4)] // case-insensitive
+    #[case("~%dell", [D,N,V,M,S], Sstr, 4)]
+    #[case("vm~**dell*", [V,M], Gl, 6)]
+    #[case("v~dell", [D,N,V,M,S], Ft, 6)]
+    #[case("~~=dell", [D,N,V,M,S], Ft, 7)]
+    #[case("!n=eDP-1", [N], Ft, 5)] // negation
+    #[case("!eDP-1", [D,N,V,M,S], Ft, 5)]
+    #[case("!w<300", [W], Lt, 3)]
     fn parse_single_search_from_str_ok(
         #[case] s: &str,
         #[case] fields: impl AsRef<[SF]>,
@@ -140,6 +264,84 @@ mod tests {
         assert_eq!(ssearch.kind, kind);
         let sfields: Vec<_> = ssearch.fields.iter().collect();
         assert_eq!(sfields, fields.as_ref());
-        assert_eq!(ssearch.pattern.as_str().len(), pattern_len);
+        assert_eq!(ssearch.pattern.to_string().len(), pattern_len);
+    }
+
+    #[rstest]
+    #[case("<600")]
+    #[case(">=600")]
+    #[case("w>=")]
+    #[case("w>abc")]
+    #[case("w<=1.5")]
+    #[case("has_mode=best")]
+    #[case("has_mode=!3840x2160@60Hz")]
+    #[case("has_mode=3840")]
+    #[case("has_mode%3840x2160")]
+    #[case("!!n=eDP-1")]
+    #[case("v~/dell")]
+    #[case("w~>5")]
+    #[case("!w>x")]
+    fn parse_single_search_from_str_err(#[case] s: &str) {
+        assert!(SingleSearch::from_str(s).is_err());
+    }
+
+    #[rstest]
+    #[case("w>600")]
+    #[case("w>=600")]
+    #[case("hw<=340")]
+    #[case("has_mode=3840x2160")]
+    #[case("has_mode=2560x1440@143.912Hz")]
+    #[case("!n=eDP-1")]
+    #[case("!%HDMI")]
+    #[case("v*DELL*")]
+    #[case("v~=dell")]
+    #[case("!~*dell?")]
+    #[case("!has_mode=3840x2160")]
+    fn display_round_trip(#[case] s: &str) {
+        let ssearch = SingleSearch::from_str(s).unwrap();
+        assert_eq!(ssearch.to_string(), s);
+    }
+
+    /// The full text search earlier versions made of a string without a search kind.
+    fn legacy_full_text(s: &str) -> SingleSearch {
+        let mut fields = FieldSet::default();
+        fields.fill_default();
+        let pattern = SearchPattern::Fulltext(s.to_string());
+        SingleSearch::new(fields, Ft, pattern, CompareMethod::AtleastOne)
+    }
+
+    #[rstest]
+    #[case("!eDP-1", "eDP-1")]
+    #[case("!n=eDP-1", "eDP-1")]
+    fn legacy_negation(#[case] legacy: &str, #[case] pattern: &str) {
+        // the exclamation mark was part of the full text, now it negates the search
+        let ssearch = SingleSearch::from_str(legacy).unwrap();
+        assert!(ssearch.negated);
+        assert_eq!(ssearch.pattern.to_string(), pattern);
+        // the full text search kind keeps the old meaning
+        let ssearch = SingleSearch::from_str(&format!("={legacy}")).unwrap();
+        assert_eq!(ssearch, legacy_full_text(legacy));
+    }
+
+    #[rstest]
+    #[case("w>600", Some(Gt))]
+    #[case("hw<=340", Some(Lt))]
+    #[case("w=600", Some(Ft))]
+    #[case("has_mode=3840x2160", Some(Ft))]
+    #[case("<600", None)]
+    #[case("has_mode=best", None)]
+    fn legacy_comparisons(#[case] legacy: &str, #[case] kind: Option<SK>) {
+        // these were full text searches, now they compare physical sizes or modes
+        match kind {
+            Some(kind) => {
+                let ssearch = SingleSearch::from_str(legacy).unwrap();
+                assert_ne!(ssearch, legacy_full_text(legacy));
+                assert_eq!(ssearch.kind, kind);
+            }
+            None => assert!(SingleSearch::from_str(legacy).is_err()),
+        }
+        // the full text search kind keeps the old meaning
+        let ssearch = SingleSearch::from_str(&format!("={legacy}")).unwrap();
+        assert_eq!(ssearch, legacy_full_text(legacy));
     }
 }
@@ -1,3 +1,8 @@
+use std::collections::VecDeque;
+
+use crate::profile::PhysicalSize;
+use crate::wl_backend::{Edid, WlMode};
+
 use super::{CompareMethod, SearchField, SingleSearch, SingleSearchResult};
 
 #[derive(Clone, Debug, PartialEq)]
@@ -8,6 +13,9 @@ pub struct SingleQuery<'a> {
     name: &'a str,
     serial: &'a str,
     vendor: &'a str,
+    edid: Option<&'a Edid>,
+    size: PhysicalSize,
+    modes: Option<&'a VecDeque<WlMode>>,
 }
 
 impl<'a> SingleQuery<'a> {
@@ -19,6 +27,9 @@ impl<'a> SingleQuery<'a> {
             name: Default::default(),
             serial: Default::default(),
             vendor: Default::default(),
+            edid: Default::default(),
+            size: Default::default(),
+            modes: Default::default(),
         }
     }
     pub fn description(mut self, description: &'a str) -> Self {
@@ -41,6 +52,18 @@ impl<'a> SingleQuery<'a> {
         self.vendor = vendor;
         self
     }
+    pub fn edid(mut self, edid: Option<&'a Edid>) -> Self {
+        self.edid = edid;
+        self
+    }
+    pub fn size(mut self, size: PhysicalSize) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn modes(mut self, modes: &'a VecDeque<WlMode>) -> Self {
+        self.modes = Some(modes);
+        self
+    }
     pub fn run(self) -> SingleSearchResult {
         let mut matches = true;
         let mut satisfied_fields: Vec<(SearchField, u64)> = vec![];
@@ -58,6 +81,35 @@ impl<'a> SingleQuery<'a> {
                 SearchField::Name => self.search.matches_name(self.name),
                 SearchField::Serial => self.search.matches_serial(self.serial),
                 SearchField::Vendor => self.search.matches_vendor(self.vendor),
+                SearchField::EdidProduct => match self.edid {
+                    Some(edid) => self
+                        .search
+                        .matches_edid_product(&edid.product_code().to_string()),
+                    None => (false, 0),
+                },
+                SearchField::EdidYear => match self.edid {
+                    Some(edid) => self
+                        .search
+                        .matches_edid_year(&edid.manufacture_year().to_string()),
+                    None => (false, 0),
+                },
+                SearchField::EdidHash => match self.edid {
+                    Some(edid) => self.search.matches_edid_hash(&edid.hash_str()),
+                    None => (false, 0),
+                },
+                // Displays without a physical size (e.g. projectors) report 0
+                SearchField::Width => match self.size.width {
+                    w if w > 0 => self.search.matches_width(&w.to_string()),
+                    _ => (false, 0),
+                },
+                SearchField::Height => match self.size.height {
+                    h if h > 0 => self.search.matches_height(&h.to_string()),
+                    _ => (false, 0),
+                },
+                SearchField::Mode => match self.modes {
+                    Some(modes) => self.search.matches_modes(modes),
+                    None => (false, 0),
+                },
             };
             matches = operation(matches, b);
             if matches {
@@ -72,9 +124,17 @@ impl<'a> SingleQuery<'a> {
 
 #[cfg(test)]
 mod test {
+    use std::collections::VecDeque;
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use crate::profile::PhysicalSize;
+    use crate::search::single::NEGATED_SPECIFICITY;
     use crate::search::{
         CompareMethod, FieldSet, SearchField, SearchKind, SearchPattern, SingleSearch,
     };
+    use crate::wl_backend::{Edid, WlBaseMode, WlGenericId, WlMode};
 
     use super::SingleQuery;
 
@@ -85,6 +145,7 @@ mod test {
             kind: SearchKind::Fulltext,
             pattern: SearchPattern::Fulltext(String::from("DP-1")),
             method: CompareMethod::Exact,
+            negated: false,
         };
         let ssr = SingleQuery::new(ssearch)
             .name("DP-1")
@@ -94,4 +155,157 @@ mod test {
 
         assert!(ssr.is_ok());
     }
+
+    #[test]
+    fn edid_search() {
+        let edid = Edid::parse(&edid_blob()).unwrap();
+        let search = |s: &str| SingleSearch::from_str(s).unwrap();
+        let query = |s: &str, edid| SingleQuery::new(search(s)).name("DP-1").edid(edid).run();
+
+        assert!(query("p=4660", Some(&edid)).is_ok());
+        assert!(query("y=2023", Some(&edid)).is_ok());
+        assert!(query(&format!("e={}", edid.hash_str()), Some(&edid)).is_ok());
+        assert!(!query("py=2023", Some(&edid)).is_ok());
+        assert!(!query("y=2022", Some(&edid)).is_ok());
+        assert!(!query("p=4660", None).is_ok());
+        // EDID fields are not part of the default search fields
+        assert!(!query("2023", Some(&edid)).is_ok());
+    }
+
+    #[test]
+    fn capability_search() {
+        let modes: VecDeque<WlMode> = [(3840, 2160, 59997), (1920, 1080, 60000)]
+            .into_iter()
+            .enumerate()
+            .map(|(id, (width, height, refresh))| {
+                let base = WlBaseMode {
+                    width,
+                    height,
+                    refresh,
+                    preferred: false,
+                };
+                WlMode::new(WlGenericId::new(id), base)
+            })
+            .collect();
+        let size = PhysicalSize {
+            width: 600,
+            height: 340,
+        };
+        let search = |s: &str| SingleSearch::from_str(s).unwrap();
+        let query = |s: &str, size| {
+            SingleQuery::new(search(s))
+                .name("DP-1")
+                .size(size)
+                .modes(&modes)
+                .run()
+        };
+
+        assert!(query("w>=600", size).is_ok());
+        assert!(!query("w>600", size).is_ok());
+        assert!(query("wh<601", size).is_ok());
+        assert!(!query("wh<341", size).is_ok());
+        assert!(query("h=340", size).is_ok());
+        assert!(!query("n>0", size).is_ok());
+        // an unknown size never matches
+        assert!(!query("w<600", PhysicalSize::default()).is_ok());
+
+        assert!(query("has_mode=3840x2160", size).is_ok());
+        assert!(query("has_mode=3840x2160@60Hz", size).is_ok());
+        assert!(!query("has_mode=3840x2160@30Hz", size).is_ok());
+        assert!(!query("has_mode=2560x1440", size).is_ok());
+
+        // predicates rank between regex and full text matches
+        let predicate = query("w>=600", size).specificity();
+        assert!(predicate < query("w=600", size).specificity());
+        assert!(predicate > query("w/600", size).specificity());
+        assert_eq!(predicate, query("has_mode=1920x1080", size).specificity());
+    }
+
+    #[rstest]
+    #[case("v*Dell*", "Dell Inc.", true)]
+    #[case("v*Dell*", "DELL", false)]
+    #[case("v*D?ll*", "Dell Inc.", true)]
+    #[case("v*D?ll", "Dell Inc.", false)]
+    #[case("v*", "", true)]
+    #[case("v**", "Dell", true)]
+    #[case("v**a*b*c", "xaybzbc", true)]
+    #[case("v**a*b*c", "xaybzbcd", false)]
+    #[case("v*a*b*c", "xaybzbc", false)]
+    #[case("v~=dell", "DELL", true)]
+    #[case("v~=DELL", "Dell", true)]
+    #[case("v~=dell", "Dell Inc.", false)]
+    #[case("v~%dell", "Dell Inc.", true)]
+    #[case("v~*DELL*", "Dell Inc.", true)]
+    #[case("v~*ell*", "Dell Inc.", false)]
+    fn glob_and_case_insensitive_search(#[case] s: &str, #[case] vendor: &str, #[case] ok: bool) {
+        let ssr = SingleQuery::new(SingleSearch::from_str(s).unwrap())
+            .vendor(vendor)
+            .run();
+        assert_eq!(ssr.is_ok(), ok);
+    }
+
+    #[rstest]
+    #[case("Dell", "v%e", "v**e*")]
+    #[case("Dell Inc.", "v%Dell", "v**Dell*")]
+    #[case("Dell Inc.", "v%Inc.", "v**Inc.")]
+    #[case("Dell Inc.", "v%l", "v**l*")]
+    fn glob_ranks_below_substring(
+        #[case] vendor: &str,
+        #[case] substring: &str,
+        #[case] glob: &str,
+    ) {
+        let query = |s: &str| {
+            SingleQuery::new(SingleSearch::from_str(s).unwrap())
+                .vendor(vendor)
+                .run()
+                .specificity()
+        };
+        assert!(query(substring) > query(glob));
+        assert!(query(glob) > query("v/Dell"));
+    }
+
+    #[test]
+    fn search_kind_weights() {
+        let query = |s: &str| {
+            SingleQuery::new(SingleSearch::from_str(s).unwrap())
+                .vendor("Dell")
+                .run()
+                .specificity()
+        };
+
+        assert!(query("v=Dell") > query("v%Del"));
+        assert!(query("v%Del") > query("v*Del*"));
+        assert!(query("v*Del*") > query("v*D*"));
+        assert!(query("v*D*") > query("v**"));
+        assert!(query("v**") > query("v/Dell"));
+        assert_eq!(query("v~=dell") * 2, query("v=Dell"));
+        assert!(query("v~**") >= query("v/Dell"));
+    }
+
+    #[test]
+    fn negated_search() {
+        let search = |s: &str| SingleSearch::from_str(s).unwrap();
+        let query = |s: &str, name| SingleQuery::new(search(s)).name(name).vendor("Foo").run();
+
+        assert!(query("!n=eDP-1", "DP-1").is_ok());
+        assert!(!query("!n=eDP-1", "eDP-1").is_ok());
+        assert!(!query("!eDP-1", "eDP-1").is_ok());
+        assert!(!query("!%DP", "eDP-1").is_ok());
+        assert!(query("!nv=Foo", "DP-1").is_ok());
+        assert!(!query("!nv=Foo", "Foo").is_ok());
+        assert_eq!(query("!n=eDP-1", "eDP-1").specificity(), 0);
+
+        let negated = query("!n=eDP-1", "DP-1").specificity();
+        assert_eq!(negated, NEGATED_SPECIFICITY);
+        assert!(negated < query("v/Foo", "DP-1").specificity());
+        assert!(negated < query("/Foo", "DP-1").specificity());
+    }
+
+    fn edid_blob() -> Vec<u8> {
+        let mut blob = vec![0u8; 128];
+        blob[..8].copy_from_slice(&[0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00]);
+        blob[10..12].copy_from_slice(&0x1234u16.to_le_bytes());
+        blob[17] = 33;
+        blob
+    }
 }
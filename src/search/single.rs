@@ -1,13 +1,23 @@
+use std::collections::VecDeque;
 use std::fmt::Display;
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use serde::{Deserialize, Serialize};
 
-use super::{CompareMethod, FieldSet, SearchKind, SearchPattern};
+use crate::wl_backend::WlMode;
+
+use super::{CompareMethod, FieldSet, SearchKind, SearchPattern, CASE_INSENSITIVE_MODIFIER};
 
 use super::{SearchField, SingleQuery};
 
+/// Prefix of a [`SingleSearch`] that matches every display its pattern does not match.
+pub const NEGATION_PREFIX: char = '!';
+/// Specificity of a satisfied negated [`SingleSearch`].
+///
+/// This is one less than a regex match on the attribute with the lowest weight.
+pub const NEGATED_SPECIFICITY: u64 = 999;
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SingleSearchResult {
     search: SingleSearch,
@@ -20,6 +30,7 @@ pub struct SingleSearch {
     pub(crate) kind: SearchKind,
     pub(crate) pattern: SearchPattern,
     pub(crate) method: CompareMethod,
+    pub(crate) negated: bool,
 }
 
 impl SingleSearchResult {
@@ -40,9 +51,36 @@ impl SingleSearchResult {
             ssfields,
             satisfied_fields,
         );
-        match self.search.method {
+        let matched = match self.search.method {
             CompareMethod::AtleastOne => !self.satisfied_fields.is_empty(),
             CompareMethod::Exact => ssfields == satisfied_fields,
+        };
+        matched != self.search.negated
+    }
+
+    pub fn search(&self) -> &SingleSearch {
+        &self.search
+    }
+
+    /// Returns the fields that made this search fail, or nothing if it is ok.
+    ///
+    /// A negated search fails because of the fields its pattern matched. A search that needs
+    /// all of its fields stops comparing at the first field that does not match.
+    pub fn failed_fields(&self) -> Vec<SearchField> {
+        if self.is_ok() {
+            return vec![];
+        }
+        if self.search.negated {
+            return self.satisfied_fields.iter().map(|f| f.0).collect();
+        }
+        let mut unsatisfied = self
+            .search
+            .fields
+            .iter()
+            .filter(|f| !self.satisfied_fields.iter().any(|(sf, _)| sf == f));
+        match self.search.method {
+            CompareMethod::AtleastOne => unsatisfied.collect(),
+            CompareMethod::Exact => unsatisfied.next().into_iter().collect(),
         }
     }
 
@@ -51,7 +89,14 @@ impl SingleSearchResult {
     /// Each [`SearchField`] rests at a certain index. `(`[`FieldSet::N`]` - 1 - index)` is taken
     /// to the power of 2 and then multiplied by the weight the [`SearchPattern::matches`] function
     /// returned.
+    ///
+    /// A negated search only tells which displays are excluded. It contributes
+    /// [`NEGATED_SPECIFICITY`] if it is ok, which is less than any positive match of a
+    /// non-empty pattern.
     pub fn specificity(&self) -> u64 {
+        if self.search.negated {
+            return if self.is_ok() { NEGATED_SPECIFICITY } else { 0 };
+        }
         self.satisfied_fields
             .iter()
             .enumerate()
@@ -72,8 +117,14 @@ impl SingleSearch {
             kind,
             pattern,
             method,
+            negated: false,
         }
     }
+    /// Inverts the result of this search.
+    pub fn negate(mut self) -> Self {
+        self.negated = !self.negated;
+        self
+    }
 
     fn matches_field(&self, text: &str, field: SearchField) -> (bool, u64) {
         if !self.fields.contains(field) {
@@ -96,6 +147,27 @@ impl SingleSearch {
     pub fn matches_vendor(&self, text: &str) -> (bool, u64) {
         self.matches_field(text, SearchField::Vendor)
     }
+    pub fn matches_edid_product(&self, text: &str) -> (bool, u64) {
+        self.matches_field(text, SearchField::EdidProduct)
+    }
+    pub fn matches_edid_year(&self, text: &str) -> (bool, u64) {
+        self.matches_field(text, SearchField::EdidYear)
+    }
+    pub fn matches_edid_hash(&self, text: &str) -> (bool, u64) {
+        self.matches_field(text, SearchField::EdidHash)
+    }
+    pub fn matches_width(&self, text: &str) -> (bool, u64) {
+        self.matches_field(text, SearchField::Width)
+    }
+    pub fn matches_height(&self, text: &str) -> (bool, u64) {
+        self.matches_field(text, SearchField::Height)
+    }
+    pub fn matches_modes(&self, modes: &VecDeque<WlMode>) -> (bool, u64) {
+        if !self.fields.contains(SearchField::Mode) {
+            return (false, 0);
+        }
+        self.pattern.matches_modes(modes)
+    }
     pub fn query<'a>(self) -> SingleQuery<'a> {
         SingleQuery::new(self)
     }
@@ -103,11 +175,17 @@ impl SingleSearch {
 
 impl Display for SingleSearch {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.negated {
+            write!(f, "{NEGATION_PREFIX}")?;
+        }
         match self.method {
             CompareMethod::AtleastOne => {}
             CompareMethod::Exact => write!(f, "{}", self.fields)?,
         }
+        if let SearchPattern::CaseInsensitive(_) = self.pattern {
+            write!(f, "{CASE_INSENSITIVE_MODIFIER}")?;
+        }
         write!(f, "{}", self.kind.as_char())?;
-        write!(f, "{}", self.pattern.as_str())
+        write!(f, "{}", self.pattern)
     }
 }
@@ -1,5 +1,6 @@
 use std::collections::VecDeque;
-use std::path::PathBuf;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 #[allow(unused_imports)]
@@ -11,19 +12,30 @@ use xdg::BaseDirectories;
 use crate::daemon::ShikaneArgs;
 use crate::error;
 use crate::profile::Profile;
+use crate::search;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Settings {
     pub profiles: VecDeque<Profile>,
     pub skip_tests: bool,
     pub oneshot: bool,
     pub timeout: Duration,
     pub config_path: PathBuf,
+    pub max_variants: Option<usize>,
+    pub max_attempts: Option<usize>,
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub confirm_timeout: Option<Duration>,
 }
 
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct SettingsToml {
     pub timeout: Option<u64>,
+    // a limit of 0 would never try any variant
+    pub max_variants: Option<NonZeroUsize>,
+    pub max_attempts: Option<NonZeroUsize>,
+    pub confirm_timeout: Option<u64>,
     #[serde(default, rename = "profile")]
     pub profiles: VecDeque<Profile>,
 }
@@ -46,6 +58,10 @@ impl Settings {
             oneshot: args.oneshot,
             timeout: Duration::from_millis(timeout),
             config_path: path,
+            max_variants: config.max_variants.map(NonZeroUsize::get),
+            max_attempts: config.max_attempts.map(NonZeroUsize::get),
+            dry_run: args.dry_run,
+            confirm_timeout: config.confirm_timeout.map(Duration::from_millis),
         }
     }
 
@@ -55,13 +71,77 @@ impl Settings {
         let (config, path) = parse_settings_toml(Some(config))?;
         self.profiles = config.profiles;
         self.config_path = path;
+        self.max_variants = config.max_variants.map(NonZeroUsize::get);
+        self.max_attempts = config.max_attempts.map(NonZeroUsize::get);
+        self.confirm_timeout = config.confirm_timeout.map(Duration::from_millis);
         Ok(())
     }
 }
 
-fn parse_settings_toml(
-    config_path: Option<PathBuf>,
-) -> Result<(SettingsToml, PathBuf), Box<dyn snafu::Error>> {
+/// Checks the config file without applying it.
+///
+/// Every problem found is printed. Returns `true` if the config can be used.
+pub fn check_config(config_path: Option<PathBuf>) -> Result<bool, Box<dyn snafu::Error>> {
+    let config_path = resolve_config_path(config_path)?;
+    let s = std::fs::read_to_string(&config_path).context(ReadConfigFileCtx)?;
+    let value: toml::Value = toml::from_str(&s).context(TomlDeserializeCtx)?;
+
+    let mut errors = 0;
+    for (location, search) in search_strings(&value) {
+        for diagnostic in search::diagnose(&search) {
+            if diagnostic.is_error() {
+                errors += 1;
+            }
+            println!("{}: {location}\n{diagnostic}\n", config_path.display());
+        }
+    }
+    // Report problems the searches are not responsible for, e.g. invalid modes.
+    if errors == 0 {
+        if let Err(err) = toml::from_str::<SettingsToml>(&s) {
+            errors += 1;
+            println!("{}: {err}", config_path.display());
+        }
+    }
+    match errors {
+        0 => println!("{}: ok", config_path.display()),
+        n => println!("{}: {n} error(s)", config_path.display()),
+    }
+    Ok(errors == 0)
+}
+
+/// Collects all search strings of a config together with a description of where they are.
+fn search_strings(value: &toml::Value) -> Vec<(String, String)> {
+    let mut searches = vec![];
+    let profiles = value.get("profile").and_then(|p| p.as_array());
+    for (pidx, profile) in profiles.into_iter().flatten().enumerate() {
+        let name = profile.get("name").and_then(|n| n.as_str()).unwrap_or("");
+        let outputs = profile.get("output").and_then(|o| o.as_array());
+        for (oidx, output) in outputs.into_iter().flatten().enumerate() {
+            let location = format!("profile {pidx} ({name:?}), output {oidx}");
+            let search = output.get("search");
+            let strings: Vec<&toml::Value> = match search {
+                Some(toml::Value::Array(array)) => array.iter().collect(),
+                Some(toml::Value::Table(table)) => table
+                    .get("any")
+                    .and_then(|a| a.as_array())
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                Some(value) => vec![value],
+                None => vec![],
+            };
+            searches.extend(
+                strings
+                    .into_iter()
+                    .filter_map(|s| s.as_str())
+                    .map(|s| (location.clone(), s.to_string())),
+            );
+        }
+    }
+    searches
+}
+
+fn resolve_config_path(config_path: Option<PathBuf>) -> Result<PathBuf, Box<dyn snafu::Error>> {
     let config_path = match config_path {
         None => {
             let xdg_dirs = BaseDirectories::with_prefix("shikane").context(BaseDirectoriesCtx)?;
@@ -72,6 +152,13 @@ fn parse_settings_toml(
         }
         Some(path) => path,
     };
+    Ok(config_path)
+}
+
+pub(crate) fn parse_settings_toml(
+    config_path: Option<PathBuf>,
+) -> Result<(SettingsToml, PathBuf), Box<dyn snafu::Error>> {
+    let config_path = resolve_config_path(config_path)?;
 
     // create empty file if it doesn't exist, ignore error
     let _ = std::fs::OpenOptions::new()
@@ -79,14 +166,38 @@ fn parse_settings_toml(
         .append(true)
         .open(config_path.clone());
     // now read from file without write permissions
-    let s = std::fs::read_to_string(config_path.clone()).context(ReadConfigFileCtx)?;
+    let config = read_settings_toml(&config_path)?;
+    Ok((config, config_path))
+}
+
+/// Like [`parse_settings_toml`] but a missing config file is an error instead of being created.
+pub(crate) fn parse_existing_settings_toml(
+    config_path: Option<PathBuf>,
+) -> Result<(SettingsToml, PathBuf), Box<dyn snafu::Error>> {
+    let config_path = match config_path {
+        Some(path) => path,
+        None => {
+            let xdg_dirs = BaseDirectories::with_prefix("shikane").context(BaseDirectoriesCtx)?;
+            xdg_dirs.get_config_home().join("config.toml")
+        }
+    };
+    ensure!(
+        config_path.exists(),
+        MissingConfigFileCtx { path: config_path }
+    );
+    let config = read_settings_toml(&config_path)?;
+    Ok((config, config_path))
+}
+
+fn read_settings_toml(config_path: &Path) -> Result<SettingsToml, SettingsError> {
+    let s = std::fs::read_to_string(config_path).context(ReadConfigFileCtx)?;
     let mut config: SettingsToml = toml::from_str(&s).context(TomlDeserializeCtx)?;
     config
         .profiles
         .iter_mut()
         .enumerate()
         .for_each(|(idx, p)| p.index = idx);
-    Ok((config, config_path))
+    Ok(config)
 }
 
 #[derive(Debug, Snafu)]
@@ -102,6 +213,8 @@ pub enum SettingsError {
         source: std::io::Error,
         location: Location,
     },
+    #[snafu(display("[{location}] Config file {path:?} does not exist"))]
+    MissingConfigFile { location: Location, path: PathBuf },
     #[snafu(display("[{location}] Cannot place config file in XDG config directory"))]
     ConfigPath {
         source: std::io::Error,
@@ -113,3 +226,50 @@ pub enum SettingsError {
         location: Location,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{search_strings, SettingsToml};
+
+    #[rstest]
+    #[case("max_variants = 0")]
+    #[case("max_attempts = 0")]
+    fn reject_zero_limits(#[case] config: &str) {
+        assert!(toml::from_str::<SettingsToml>(config).is_err());
+        let config = config.replace('0', "1");
+        assert!(toml::from_str::<SettingsToml>(&config).is_ok());
+    }
+
+    #[test]
+    fn collect_search_strings() {
+        let value: toml::Value = toml::from_str(
+            r#"
+            [[profile]]
+            name = "a"
+            output = [
+                { search = "DP-1", enable = true },
+                { search = ["n=DP-2", "v=Foo"], enable = true },
+                { search = { any = ["s=1", "s=2"] }, enable = true },
+                { enable = true },
+            ]
+            "#,
+        )
+        .unwrap();
+        let searches: Vec<_> = search_strings(&value)
+            .into_iter()
+            .map(|(location, search)| format!("{location}: {search}"))
+            .collect();
+        assert_eq!(
+            searches,
+            [
+                r#"profile 0 ("a"), output 0: DP-1"#,
+                r#"profile 0 ("a"), output 1: n=DP-2"#,
+                r#"profile 0 ("a"), output 1: v=Foo"#,
+                r#"profile 0 ("a"), output 2: s=1"#,
+                r#"profile 0 ("a"), output 2: s=2"#,
+            ]
+        );
+    }
+}
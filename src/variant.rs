@@ -1,15 +1,16 @@
 use std::fmt::Display;
+use std::sync::Arc;
 
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use serde::{Deserialize, Serialize};
 
-use crate::matching::Pairing;
+use crate::matching::{Pairing, PairingConfig};
 use crate::profile::Profile;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ValidVariant {
-    pub profile: Profile,
+    pub profile: Arc<Profile>,
     pub pairings: Vec<Pairing>,
     pub state: VariantState,
     pub index: usize,
@@ -72,6 +73,14 @@ impl ValidVariant {
     pub fn specificity(&self) -> u64 {
         self.pairings.iter().map(|p| p.specificity()).sum::<u64>() / self.pairings.len() as u64
     }
+    /// Returns the settings of every head, ordered by head.
+    ///
+    /// Variants with equal configurations leave the heads in the same state.
+    pub fn config(&self) -> Vec<PairingConfig> {
+        let mut config: Vec<_> = self.pairings.iter().map(Pairing::config).collect();
+        config.sort_by_key(|c| c.head());
+        config
+    }
     pub fn idx_str(&self) -> String {
         format!("{},{}", self.profile.index, self.index)
     }
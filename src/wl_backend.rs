@@ -1,3 +1,7 @@
+mod edid;
+mod mock;
+mod record;
+mod snapshot;
 mod wl_store;
 
 use std::collections::VecDeque;
@@ -11,6 +15,15 @@ use wayland_client::backend::WaylandError;
 use crate::profile::{AdaptiveSyncState, PhysicalSize, Position, Transform};
 use crate::variant::ValidVariant;
 
+pub use self::edid::{Edid, EdidError, EdidReader};
+pub use self::mock::{
+    MockBackend, MockBackendError, MockHotplug, MockHotplugEvent, MockRequest, MockRequestKind,
+    MockResponse, MockRule, MockScript,
+};
+pub use self::record::{
+    Divergence, Record, RecordEntry, RecordError, RecordedRequest, RecordingBackend, ReplayBackend,
+};
+pub use self::snapshot::HeadSnapshot;
 pub use self::wl_store::{ForeignId, WlStore};
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -23,12 +36,30 @@ pub trait WlBackend {
     fn drain_event_queue(&mut self) -> VecDeque<WlBackendEvent>;
     fn export_heads(&self) -> Option<VecDeque<WlHead>>;
     fn flush(&self) -> Result<(), WaylandError>;
+
+    /// Records an input of the daemon that does not come from the backend, see
+    /// [`RecordingBackend`]. Other backends ignore it.
+    fn record_input(&self, _entry: RecordEntry) {}
+
+    /// Applies the state of the heads in the snapshot, responding like [`WlBackend::apply`].
+    fn apply_snapshot(&mut self, snapshot: &HeadSnapshot) -> Result<(), WlConfigurationError> {
+        let heads = self.export_heads().unwrap_or_default();
+        let missing = snapshot.missing_heads(&heads);
+        if !missing.is_empty() {
+            let heads = missing.join(", ");
+            return DisconnectedSnapshotHeadsCtx { heads }.fail();
+        }
+        let variant = snapshot.variant(heads).context(SnapshotMismatchCtx)?;
+        self.apply(&variant)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WlHead {
     base: WlBaseHead,
+    #[serde(default)]
     current_mode: Option<WlMode>,
+    #[serde(default)]
     modes: VecDeque<WlMode>,
     pub(crate) id: WlGenericId,
 }
@@ -40,6 +71,7 @@ pub struct WlMode {
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WlBaseHead {
     pub name: String,
     pub description: String,
@@ -52,9 +84,11 @@ pub struct WlBaseHead {
     pub model: String,
     pub serial_number: String,
     pub adaptive_sync: Option<AdaptiveSyncState>,
+    pub edid: Option<Edid>,
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WlBaseMode {
     pub width: i32,
     pub height: i32,
@@ -62,7 +96,7 @@ pub struct WlBaseMode {
     pub preferred: bool,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum WlBackendEvent {
     AtomicChangeDone,
     NeededResourceFinished,
@@ -103,9 +137,34 @@ pub enum WlConfigurationError {
         location: Location,
         mode: WlBaseMode,
     },
+    #[snafu(display("[{location}] The snapshot does not fit the connected heads anymore"))]
+    SnapshotMismatch { location: Location },
+    #[snafu(display("[{location}] Heads of the snapshot are not connected anymore: {heads}"))]
+    DisconnectedSnapshotHeads { location: Location, heads: String },
+    #[snafu(display("[{location}] Recorded error: {message}"))]
+    Recorded { location: Location, message: String },
+}
+
+impl WlGenericId {
+    pub fn new(id: usize) -> Self {
+        Self(id)
+    }
 }
 
 impl WlHead {
+    pub fn new(
+        id: WlGenericId,
+        base: WlBaseHead,
+        modes: VecDeque<WlMode>,
+        current_mode: Option<WlMode>,
+    ) -> Self {
+        Self {
+            base,
+            current_mode,
+            modes,
+            id,
+        }
+    }
     pub fn name(&self) -> &str {
         &self.base.name
     }
@@ -142,15 +201,35 @@ impl WlHead {
     pub fn serial_number(&self) -> &str {
         &self.base.serial_number
     }
+    /// Replaces the serial number with `serial` wherever it shows up and the EDID hash, which is
+    /// unique to the display, with the hash of `edid_seed`.
+    ///
+    /// An empty serial number stays empty, so searches keep behaving the same.
+    pub fn anonymize(&mut self, serial: &str, edid_seed: &str) {
+        let base = &mut self.base;
+        if !base.serial_number.is_empty() {
+            base.description = base.description.replace(&base.serial_number, serial);
+            base.serial_number = serial.to_string();
+        }
+        if let Some(edid) = base.edid.as_mut() {
+            edid.anonymize(edid_seed);
+        }
+    }
     pub fn adaptive_sync(&self) -> Option<AdaptiveSyncState> {
         self.base.adaptive_sync
     }
+    pub fn edid(&self) -> Option<&Edid> {
+        self.base.edid.as_ref()
+    }
     pub fn wl_base_head(&self) -> &WlBaseHead {
         &self.base
     }
 }
 
 impl WlMode {
+    pub fn new(id: WlGenericId, base: WlBaseMode) -> Self {
+        Self { base, id }
+    }
     pub fn width(&self) -> i32 {
         self.base.width
     }
@@ -214,3 +293,95 @@ impl<'a> Hash for LessEqWlHead<'a> {
         self.0.name().hash(state);
     }
 }
+
+/// Identifies a head across reconnects and restarts of the compositor.
+fn head_key(head: &WlHead) -> String {
+    format!(
+        "{}|{}|{}|{}",
+        head.make(),
+        head.model(),
+        head.serial_number(),
+        head.name()
+    )
+}
+
+/// Identifies a set of heads regardless of their order.
+pub(crate) fn head_set_key(heads: &VecDeque<WlHead>) -> Vec<String> {
+    let mut key: Vec<String> = heads.iter().map(head_key).collect();
+    key.sort();
+    key
+}
+
+/// Heads for the tests of the other modules.
+#[cfg(test)]
+pub(crate) mod test_heads {
+    use std::collections::VecDeque;
+
+    use super::{WlBaseHead, WlBaseMode, WlGenericId, WlHead, WlMode};
+
+    /// A head with 1920x1080 modes at these refresh rates, the first one is preferred.
+    pub(crate) fn head(id: usize, name: &str, refresh_rates: &[i32]) -> WlHead {
+        let base = WlBaseHead {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        head_with_base(id, base, refresh_rates)
+    }
+
+    /// Like [`head`] but with the name, make, serial number etc. taken from `base`.
+    pub(crate) fn head_with_base(id: usize, base: WlBaseHead, refresh_rates: &[i32]) -> WlHead {
+        let modes = refresh_rates
+            .iter()
+            .enumerate()
+            .map(|(idx, &refresh)| {
+                let base = WlBaseMode {
+                    width: 1920,
+                    height: 1080,
+                    refresh,
+                    preferred: idx == 0,
+                };
+                WlMode::new(WlGenericId::new(id * 100 + idx), base)
+            })
+            .collect();
+        WlHead::new(WlGenericId::new(id), base, modes, None)
+    }
+
+    /// Heads named DP-0, DP-1 and so on without any modes.
+    pub(crate) fn dp_heads(count: usize) -> VecDeque<WlHead> {
+        (0..count)
+            .map(|id| head(id, &format!("DP-{id}"), &[]))
+            .collect()
+    }
+
+    /// Makes the mode at `idx` the current one.
+    pub(crate) fn set_current_mode(head: &mut WlHead, idx: usize) {
+        head.current_mode = head.modes.get(idx).cloned();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_heads::head_with_base;
+    use super::WlBaseHead;
+
+    #[test]
+    fn anonymize_serial_number() {
+        let head = |serial: &str| {
+            let base = WlBaseHead {
+                description: format!("Dell Inc. DELL U2720Q {serial} (DP-1)"),
+                serial_number: serial.to_string(),
+                ..Default::default()
+            };
+            head_with_base(1, base, &[])
+        };
+
+        let mut h = head("ABC123");
+        h.anonymize("SERIAL0", "EDID0");
+        assert_eq!(h.serial_number(), "SERIAL0");
+        assert_eq!(h.description(), "Dell Inc. DELL U2720Q SERIAL0 (DP-1)");
+
+        let mut h = head("");
+        h.anonymize("SERIAL0", "EDID0");
+        assert_eq!(h.serial_number(), "");
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use snafu::{prelude::*, Location};
+
+const SYSFS_DRM_ROOT: &str = "/sys/class/drm";
+const EDID_HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+const EDID_BLOCK_LEN: usize = 128;
+
+/// Properties of a display taken from its EDID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Edid {
+    product_code: u16,
+    manufacture_year: u16,
+    hash: u64,
+}
+
+/// Reads the EDID of a connector from sysfs.
+#[derive(Clone, Debug)]
+pub struct EdidReader {
+    sysfs_root: PathBuf,
+}
+
+impl Edid {
+    pub fn parse(blob: &[u8]) -> Result<Self, EdidError> {
+        if blob.len() < EDID_BLOCK_LEN {
+            return TooShortCtx { len: blob.len() }.fail();
+        }
+        if blob[..EDID_HEADER.len()] != EDID_HEADER {
+            return InvalidHeaderCtx {}.fail();
+        }
+        Ok(Self {
+            product_code: u16::from_le_bytes([blob[10], blob[11]]),
+            // If the week is set to 0xFF, this byte contains the model year instead.
+            manufacture_year: 1990 + blob[17] as u16,
+            hash: fnv1a(blob),
+        })
+    }
+    pub fn product_code(&self) -> u16 {
+        self.product_code
+    }
+    pub fn manufacture_year(&self) -> u16 {
+        self.manufacture_year
+    }
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+    /// Replaces the hash, as it identifies a single display.
+    pub fn anonymize(&mut self, seed: &str) {
+        self.hash = fnv1a(seed.as_bytes());
+    }
+    /// The hash of the whole EDID blob as hexadecimal string.
+    pub fn hash_str(&self) -> String {
+        format!("{:016x}", self.hash)
+    }
+}
+
+impl EdidReader {
+    pub fn new(sysfs_root: PathBuf) -> Self {
+        Self { sysfs_root }
+    }
+
+    /// Read and parse the EDID of the connector with the given name.
+    ///
+    /// The EDID is looked up at `<sysfs_root>/card*-<connector>/edid`.
+    pub fn read(&self, connector: &str) -> Result<Edid, EdidError> {
+        let path = self.find_edid_path(connector)?;
+        let blob = std::fs::read(&path).context(ReadCtx { path })?;
+        Edid::parse(&blob)
+    }
+
+    fn find_edid_path(&self, connector: &str) -> Result<PathBuf, EdidError> {
+        let root = &self.sysfs_root;
+        let entries = std::fs::read_dir(root).context(ReadCtx { path: root })?;
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name())
+            .find(|name| is_card_connector(&name.to_string_lossy(), connector))
+            .map(|name| root.join(name).join("edid"))
+            .context(ConnectorNotFoundCtx { connector })
+    }
+}
+
+impl Default for EdidReader {
+    fn default() -> Self {
+        Self::new(PathBuf::from(SYSFS_DRM_ROOT))
+    }
+}
+
+/// Returns true if `name` has the form `card<N>-<connector>`.
+fn is_card_connector(name: &str, connector: &str) -> bool {
+    let Some(rest) = name.strip_prefix("card") else {
+        return false;
+    };
+    let Some((card, con)) = rest.split_once('-') else {
+        return false;
+    };
+    !card.is_empty() && card.chars().all(|c| c.is_ascii_digit()) && con == connector
+}
+
+/// 64 bit FNV-1a hash. Unlike the hasher of the std library it is stable across releases,
+/// which matters because the hash ends up in config files.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl Display for Edid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "product code: {}, year: {}, hash: {}",
+            self.product_code,
+            self.manufacture_year,
+            self.hash_str()
+        )
+    }
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(context(suffix(Ctx)))]
+#[snafu(visibility(pub(crate)))]
+pub enum EdidError {
+    #[snafu(display("[{location}] Cannot read {path:?}"))]
+    Read {
+        source: std::io::Error,
+        location: Location,
+        path: PathBuf,
+    },
+    #[snafu(display("[{location}] Cannot find a DRM connector named {connector:?}"))]
+    ConnectorNotFound {
+        location: Location,
+        connector: String,
+    },
+    #[snafu(display("[{location}] EDID is too short: {len} bytes"))]
+    TooShort { location: Location, len: usize },
+    #[snafu(display("[{location}] EDID has an invalid header"))]
+    InvalidHeader { location: Location },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    fn blob(product_code: u16, year: u8) -> Vec<u8> {
+        let mut blob = vec![0u8; EDID_BLOCK_LEN];
+        blob[..8].copy_from_slice(&EDID_HEADER);
+        blob[10..12].copy_from_slice(&product_code.to_le_bytes());
+        blob[17] = year;
+        blob
+    }
+
+    /// A fake sysfs directory, removed again when dropped.
+    struct SysfsFixture(PathBuf);
+
+    impl SysfsFixture {
+        fn new(test: &str, entries: &[(&str, Vec<u8>)]) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("shikane-edid-{}-{test}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            for (name, blob) in entries {
+                let dir = root.join(name);
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::write(dir.join("edid"), blob).unwrap();
+            }
+            Self(root)
+        }
+    }
+
+    impl Drop for SysfsFixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[rstest]
+    #[case("card0-DP-1", "DP-1", true)]
+    #[case("card12-HDMI-A-1", "HDMI-A-1", true)]
+    #[case("card0-DP-1", "DP-2", false)]
+    #[case("card0-DP-10", "DP-1", false)]
+    #[case("card-DP-1", "DP-1", false)]
+    #[case("cardX-DP-1", "DP-1", false)]
+    #[case("renderD128", "DP-1", false)]
+    fn match_card_connector(#[case] name: &str, #[case] connector: &str, #[case] ok: bool) {
+        assert_eq!(is_card_connector(name, connector), ok);
+    }
+
+    #[test]
+    fn parse_edid() {
+        let edid = Edid::parse(&blob(0x1234, 33)).unwrap();
+        assert_eq!(edid.product_code(), 0x1234);
+        assert_eq!(edid.manufacture_year(), 2023);
+        assert_eq!(edid.hash_str().len(), 16);
+        assert_ne!(edid.hash(), Edid::parse(&blob(0x1235, 33)).unwrap().hash());
+    }
+
+    #[test]
+    fn parse_edid_err() {
+        assert!(matches!(
+            Edid::parse(&blob(1, 1)[..100]),
+            Err(EdidError::TooShort { len: 100, .. })
+        ));
+        let mut invalid = blob(1, 1);
+        invalid[0] = 0xFF;
+        assert!(matches!(
+            Edid::parse(&invalid),
+            Err(EdidError::InvalidHeader { .. })
+        ));
+    }
+
+    #[test]
+    fn read_edid_from_sysfs() {
+        let fixture = SysfsFixture::new(
+            "read",
+            &[
+                ("card1-DP-1", blob(1, 30)),
+                ("card1-DP-2", blob(2, 31)),
+                ("card1-eDP-1", vec![]),
+            ],
+        );
+        let reader = EdidReader::new(fixture.0.clone());
+        assert_eq!(reader.read("DP-2").unwrap().product_code(), 2);
+        assert_eq!(reader.read("DP-1").unwrap().manufacture_year(), 2020);
+        assert!(matches!(
+            reader.read("eDP-1"),
+            Err(EdidError::TooShort { len: 0, .. })
+        ));
+        assert!(matches!(
+            reader.read("HDMI-A-1"),
+            Err(EdidError::ConnectorNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use calloop::ping::{make_ping, Ping, PingSource};
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use snafu::{prelude::*, Location};
+use wayland_client::backend::WaylandError;
+
+use crate::variant::ValidVariant;
+
+use super::{DeadHeadCtx, WlBackend, WlBackendEvent, WlConfigurationError, WlHead};
+
+/// A backend without a compositor, its heads and responses are defined by a [`MockScript`].
+#[derive(Debug)]
+pub struct MockBackend {
+    heads: VecDeque<WlHead>,
+    rules: Vec<MockRule>,
+    hotplug: VecDeque<MockHotplug>,
+    event_queue: VecDeque<WlBackendEvent>,
+    requests: Vec<MockRequest>,
+    ping: Option<Ping>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MockScript {
+    /// The heads that are connected at startup
+    pub heads: Vec<WlHead>,
+    /// Responses to test and apply requests, every variant not matched by any rule succeeds
+    #[serde(default)]
+    pub responses: Vec<MockRule>,
+    /// Changes of the connected heads, in the order they happen
+    #[serde(default)]
+    pub hotplug: Vec<MockHotplug>,
+}
+
+/// Responds to the variants of a profile.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MockRule {
+    pub profile: String,
+    /// Only match the variant with this index
+    #[serde(default)]
+    pub variant: Option<usize>,
+    pub response: MockResponse,
+    /// Only respond this many times
+    #[serde(default)]
+    pub times: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MockResponse {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MockHotplug {
+    /// Milliseconds after the daemon has dealt with the previous step
+    pub delay: u64,
+    pub event: MockHotplugEvent,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum MockHotplugEvent {
+    Connect(Box<WlHead>),
+    /// Disconnect the head with this name
+    Disconnect(String),
+    /// Make the daemon shut down, like a compositor that exits
+    Finish,
+}
+
+/// A test or apply request the backend has responded to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockRequest {
+    pub kind: MockRequestKind,
+    pub profile: String,
+    pub variant: usize,
+    pub response: MockResponse,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MockRequestKind {
+    Test,
+    Apply,
+}
+
+impl WlBackend for MockBackend {
+    fn apply(&mut self, variant: &ValidVariant) -> Result<(), WlConfigurationError> {
+        self.respond(MockRequestKind::Apply, variant)
+    }
+
+    fn test(&mut self, variant: &ValidVariant) -> Result<(), WlConfigurationError> {
+        self.respond(MockRequestKind::Test, variant)
+    }
+
+    fn drain_event_queue(&mut self) -> VecDeque<WlBackendEvent> {
+        std::mem::take(&mut self.event_queue)
+    }
+
+    fn export_heads(&self) -> Option<VecDeque<WlHead>> {
+        Some(self.heads.clone())
+    }
+
+    fn flush(&self) -> Result<(), WaylandError> {
+        Ok(())
+    }
+}
+
+impl MockBackend {
+    pub fn new(script: MockScript) -> Self {
+        Self {
+            heads: script.heads.into(),
+            rules: script.responses,
+            hotplug: script.hotplug.into(),
+            // the heads are announced like a compositor does after connecting
+            event_queue: [WlBackendEvent::AtomicChangeDone].into(),
+            requests: Default::default(),
+            ping: None,
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, MockBackendError> {
+        let content = std::fs::read_to_string(path).context(ReadScriptCtx { path })?;
+        let script: MockScript = ron::de::from_str(&content).context(ParseScriptCtx)?;
+        Ok(Self::new(script))
+    }
+
+    /// Returns a source that wakes the event loop whenever new events are queued.
+    pub fn event_source(&mut self) -> Result<PingSource, MockBackendError> {
+        let (ping, source) = make_ping().context(PingCtx)?;
+        if !self.event_queue.is_empty() {
+            ping.ping();
+        }
+        self.ping = Some(ping);
+        Ok(source)
+    }
+
+    /// Removes the next hotplug step of the script, the event loop schedules it.
+    pub fn next_hotplug_step(&mut self) -> Option<MockHotplug> {
+        self.hotplug.pop_front()
+    }
+
+    /// Returns true if events are queued that the daemon has not drained yet.
+    pub fn has_queued_events(&self) -> bool {
+        !self.event_queue.is_empty()
+    }
+
+    /// All requests in the order they were responded to.
+    pub fn requests(&self) -> &[MockRequest] {
+        &self.requests
+    }
+
+    pub fn hotplug(&mut self, event: &MockHotplugEvent) {
+        match event {
+            MockHotplugEvent::Connect(head) => {
+                info!("mock: connecting head {:?}", head.name());
+                self.heads.push_back(*head.clone());
+            }
+            MockHotplugEvent::Disconnect(name) => {
+                info!("mock: disconnecting head {:?}", name);
+                self.heads.retain(|h| h.name() != name);
+            }
+            MockHotplugEvent::Finish => {
+                info!("mock: finishing");
+                self.queue_event(WlBackendEvent::NeededResourceFinished);
+                return;
+            }
+        }
+        self.queue_event(WlBackendEvent::AtomicChangeDone);
+    }
+
+    fn respond(
+        &mut self,
+        kind: MockRequestKind,
+        variant: &ValidVariant,
+    ) -> Result<(), WlConfigurationError> {
+        for pairing in variant.pairings.iter() {
+            let head_name = pairing.wl_head().name();
+            if !self.heads.iter().any(|h| h.id == pairing.wl_head().id) {
+                return DeadHeadCtx { head_name }.fail();
+            }
+        }
+
+        let profile = &variant.profile.name;
+        let response = self
+            .rules
+            .iter_mut()
+            .find(|r| {
+                r.profile == *profile
+                    && r.variant.map_or(true, |idx| idx == variant.index)
+                    && r.times != Some(0)
+            })
+            .map_or(MockResponse::Succeeded, |rule| {
+                rule.times = rule.times.map(|t| t - 1);
+                rule.response
+            });
+        info!(
+            "mock: {kind:?} of variant {}:{profile:?} {response:?}",
+            variant.idx_str()
+        );
+        self.requests.push(MockRequest {
+            kind,
+            profile: profile.clone(),
+            variant: variant.index,
+            response,
+        });
+
+        match response {
+            MockResponse::Succeeded => self.queue_event(WlBackendEvent::Succeeded),
+            MockResponse::Failed => self.queue_event(WlBackendEvent::Failed),
+            MockResponse::Cancelled => self.queue_event(WlBackendEvent::Cancelled),
+        }
+        if kind == MockRequestKind::Apply && response == MockResponse::Succeeded {
+            self.configure_heads(variant);
+            self.queue_event(WlBackendEvent::AtomicChangeDone);
+        }
+        Ok(())
+    }
+
+    /// Changes the state of the heads like the compositor would after applying the variant.
+    fn configure_heads(&mut self, variant: &ValidVariant) {
+        for pairing in variant.pairings.iter() {
+            let Some(head) = self.heads.iter_mut().find(|h| h.id == pairing.wl_head().id) else {
+                continue;
+            };
+            let output = pairing.output();
+            head.base.enabled = output.enable;
+            if !output.enable {
+                continue;
+            }
+            if let Some(wl_mode) = pairing.wl_mode() {
+                head.current_mode = head.modes.iter().find(|m| m.id == wl_mode.id).cloned();
+            }
+            if let Some(position) = output.position {
+                head.base.position = position;
+            }
+            if let Some(scale) = output.scale {
+                head.base.scale = scale;
+            }
+            if let Some(transform) = output.transform {
+                head.base.transform = Some(transform);
+            }
+            if let Some(adaptive_sync) = output.adaptive_sync {
+                head.base.adaptive_sync = Some(adaptive_sync);
+            }
+        }
+    }
+
+    fn queue_event(&mut self, event: WlBackendEvent) {
+        trace!("mock: queueing event {event}");
+        self.event_queue.push_back(event);
+        if let Some(ping) = &self.ping {
+            ping.ping();
+        }
+    }
+}
+
+impl AsMut<MockBackend> for MockBackend {
+    fn as_mut(&mut self) -> &mut MockBackend {
+        self
+    }
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(context(suffix(Ctx)))]
+#[snafu(visibility(pub(crate)))]
+pub enum MockBackendError {
+    #[snafu(display("[{location}] Cannot read mock script {path:?}"))]
+    ReadScript {
+        source: std::io::Error,
+        location: Location,
+        path: PathBuf,
+    },
+    #[snafu(display("[{location}] Cannot parse mock script"))]
+    ParseScript {
+        source: ron::error::SpannedError,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot create the event source of the mock backend"))]
+    Ping {
+        source: std::io::Error,
+        location: Location,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MockHotplugEvent, MockResponse, MockScript};
+
+    #[test]
+    fn parse_script() {
+        let script: MockScript = ron::de::from_str(
+            r#"(
+                heads: [
+                    (
+                        id: (0),
+                        base: (name: "eDP-1", make: "BOE", enabled: true, scale: 1.0),
+                        modes: [
+                            (id: (1), base: (width: 1920, height: 1080, refresh: 60000)),
+                        ],
+                    ),
+                ],
+                responses: [(profile: "desk", variant: Some(0), response: Failed)],
+                hotplug: [
+                    (delay: 1000, event: Connect((id: (2), base: (name: "DP-1")))),
+                    (delay: 1000, event: Disconnect("DP-1")),
+                    (delay: 500, event: Finish),
+                ],
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(script.heads[0].name(), "eDP-1");
+        assert_eq!(script.heads[0].modes()[0].refresh(), 60000);
+        assert_eq!(script.responses[0].response, MockResponse::Failed);
+        assert!(script.responses[0].times.is_none());
+        assert!(matches!(
+            script.hotplug[1].event,
+            MockHotplugEvent::Disconnect(ref name) if name == "DP-1"
+        ));
+    }
+}
@@ -0,0 +1,387 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use snafu::{prelude::*, Location};
+use wayland_client::backend::WaylandError;
+
+use crate::daemon::pins::PersistedPin;
+use crate::error;
+use crate::ipc::IpcRequest;
+use crate::settings::Settings;
+use crate::variant::ValidVariant;
+
+use super::{RecordedCtx, WlBackend, WlBackendEvent, WlConfigurationError, WlHead};
+
+/// Wraps a backend and writes everything passing between it and the daemon to a file.
+pub struct RecordingBackend<B: WlBackend> {
+    inner: B,
+    file: File,
+    start: Instant,
+}
+
+/// Feeds a recording into the daemon and checks that it makes the recorded requests.
+#[derive(Debug)]
+pub struct ReplayBackend {
+    records: RefCell<VecDeque<Record>>,
+    replayed: Cell<usize>,
+    divergence: RefCell<Option<Divergence>>,
+}
+
+/// One line of a recording.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Record {
+    /// Milliseconds since the recording started
+    pub time: u64,
+    pub entry: RecordEntry,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum RecordEntry {
+    /// The settings and the pinned profiles the daemon was started with, always the first record
+    Settings(Settings, Vec<PersistedPin>),
+    /// Events in the order the daemon processed them
+    Events(VecDeque<WlBackendEvent>),
+    Heads(Option<VecDeque<WlHead>>),
+    Test(RecordedRequest),
+    Apply(RecordedRequest),
+    /// The reports of the profiles matched against the heads arrived
+    Matched,
+    /// A request received over IPC
+    Request(IpcRequest),
+    /// The settings after the config has been reloaded, the file is not read again on replay
+    Reload(Settings),
+    /// The timer of the confirmation with this id expired before it was confirmed
+    ConfirmTimeout(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub variant: String,
+    pub profile: String,
+    /// The error returned by the backend
+    pub error: Option<String>,
+}
+
+/// The first request of the replayed daemon that differs from the recording.
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    /// Line of the recording
+    pub line: usize,
+    pub time: Option<u64>,
+    pub expected: String,
+    pub got: String,
+}
+
+impl<B: WlBackend> WlBackend for RecordingBackend<B> {
+    fn apply(&mut self, variant: &ValidVariant) -> Result<(), WlConfigurationError> {
+        let result = self.inner.apply(variant);
+        self.record(RecordEntry::Apply(RecordedRequest::new(variant, &result)));
+        result
+    }
+
+    fn test(&mut self, variant: &ValidVariant) -> Result<(), WlConfigurationError> {
+        let result = self.inner.test(variant);
+        self.record(RecordEntry::Test(RecordedRequest::new(variant, &result)));
+        result
+    }
+
+    // the events are recorded once they are processed, see [`WlBackend::record_input`]
+    fn drain_event_queue(&mut self) -> VecDeque<WlBackendEvent> {
+        self.inner.drain_event_queue()
+    }
+
+    fn export_heads(&self) -> Option<VecDeque<WlHead>> {
+        let heads = self.inner.export_heads();
+        self.record(RecordEntry::Heads(heads.clone()));
+        heads
+    }
+
+    fn flush(&self) -> Result<(), WaylandError> {
+        self.inner.flush()
+    }
+
+    fn record_input(&self, entry: RecordEntry) {
+        self.record(entry)
+    }
+}
+
+impl<B: WlBackend> RecordingBackend<B> {
+    pub fn new(
+        inner: B,
+        path: &Path,
+        settings: &Settings,
+        pins: &[PersistedPin],
+    ) -> Result<Self, RecordError> {
+        let file = File::create(path).context(CreateRecordingCtx { path })?;
+        let backend = Self {
+            inner,
+            file,
+            start: Instant::now(),
+        };
+        backend.record(RecordEntry::Settings(settings.clone(), pins.to_vec()));
+        Ok(backend)
+    }
+
+    // Errors are only logged, the daemon keeps running without a complete recording.
+    fn record(&self, entry: RecordEntry) {
+        let record = Record {
+            time: self.start.elapsed().as_millis() as u64,
+            entry,
+        };
+        let result = ron::ser::to_string(&record)
+            .context(SerializeRecordCtx)
+            .and_then(|line| writeln!(&self.file, "{line}").context(WriteRecordCtx));
+        if let Err(err) = result {
+            warn!("{}", error::report(&err));
+        }
+    }
+}
+
+impl<B: WlBackend> AsMut<B> for RecordingBackend<B> {
+    fn as_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+}
+
+impl WlBackend for ReplayBackend {
+    fn apply(&mut self, variant: &ValidVariant) -> Result<(), WlConfigurationError> {
+        self.replay_request(RecordEntry::Apply(RecordedRequest::new(variant, &Ok(()))))
+    }
+
+    fn test(&mut self, variant: &ValidVariant) -> Result<(), WlConfigurationError> {
+        self.replay_request(RecordEntry::Test(RecordedRequest::new(variant, &Ok(()))))
+    }
+
+    fn drain_event_queue(&mut self) -> VecDeque<WlBackendEvent> {
+        // events are fed by the caller, see [`ReplayBackend::next_events`]
+        Default::default()
+    }
+
+    fn export_heads(&self) -> Option<VecDeque<WlHead>> {
+        match self.next_record() {
+            Some(Record {
+                entry: RecordEntry::Heads(heads),
+                ..
+            }) => heads,
+            other => {
+                self.diverge(other, "export of heads".to_string());
+                None
+            }
+        }
+    }
+
+    fn flush(&self) -> Result<(), WaylandError> {
+        Ok(())
+    }
+}
+
+impl ReplayBackend {
+    /// Reads a recording, returning the recorded settings and pins and a backend replaying the
+    /// rest.
+    pub fn from_file(path: &Path) -> Result<(Settings, Vec<PersistedPin>, Self), RecordError> {
+        let content = std::fs::read_to_string(path).context(ReadRecordingCtx { path })?;
+        let mut records = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| ron::de::from_str(line).context(ParseRecordCtx { line: idx + 1 }))
+            .collect::<Result<VecDeque<Record>, _>>()?;
+        let (settings, pins) = match records.pop_front() {
+            Some(Record {
+                entry: RecordEntry::Settings(settings, pins),
+                ..
+            }) => (settings, pins),
+            _ => return MissingSettingsCtx.fail(),
+        };
+        let backend = Self {
+            records: RefCell::new(records),
+            replayed: Cell::new(1),
+            divergence: Default::default(),
+        };
+        Ok((settings, pins, backend))
+    }
+
+    /// Removes the next input of the daemon, e.g. events of the backend. Returns `None` if the
+    /// daemon is expected to make a request next.
+    pub fn next_input(&self) -> Option<RecordEntry> {
+        if self.divergence.borrow().is_some() {
+            return None;
+        }
+        let mut records = self.records.borrow_mut();
+        if !records.front().is_some_and(|r| r.entry.is_input()) {
+            return None;
+        }
+        let record = records.pop_front()?;
+        self.replayed.set(self.replayed.get() + 1);
+        Some(record.entry)
+    }
+
+    /// Marks the rest of the recording as diverged, if anything is left.
+    pub fn finish(&self) {
+        if self.divergence.borrow().is_some() {
+            return;
+        }
+        let record = self.next_record();
+        if record.is_some() {
+            self.diverge(record, "no more requests".to_string());
+        }
+    }
+
+    pub fn replayed(&self) -> usize {
+        self.replayed.get()
+    }
+
+    pub fn divergence(&self) -> Option<Divergence> {
+        self.divergence.borrow().clone()
+    }
+
+    fn replay_request(&self, got: RecordEntry) -> Result<(), WlConfigurationError> {
+        let record = self.next_record();
+        let same = match (record.as_ref().map(|r| &r.entry), &got) {
+            (Some(RecordEntry::Test(recorded)), RecordEntry::Test(request))
+            | (Some(RecordEntry::Apply(recorded)), RecordEntry::Apply(request)) => {
+                (&recorded.variant, &recorded.profile) == (&request.variant, &request.profile)
+            }
+            _ => false,
+        };
+        if !same {
+            self.diverge(record, got.to_string());
+            return Ok(());
+        }
+        match record.map(|r| r.entry) {
+            Some(RecordEntry::Test(RecordedRequest {
+                error: Some(message),
+                ..
+            }))
+            | Some(RecordEntry::Apply(RecordedRequest {
+                error: Some(message),
+                ..
+            })) => RecordedCtx { message }.fail(),
+            _ => Ok(()),
+        }
+    }
+
+    fn next_record(&self) -> Option<Record> {
+        if self.divergence.borrow().is_some() {
+            return None;
+        }
+        let record = self.records.borrow_mut().pop_front();
+        if record.is_some() {
+            self.replayed.set(self.replayed.get() + 1);
+        }
+        record
+    }
+
+    fn diverge(&self, expected: Option<Record>, got: String) {
+        if self.divergence.borrow().is_some() {
+            return;
+        }
+        let divergence = Divergence {
+            line: self.replayed(),
+            time: expected.as_ref().map(|r| r.time),
+            expected: match expected {
+                Some(record) => record.entry.to_string(),
+                None => "end of recording".to_string(),
+            },
+            got,
+        };
+        *self.divergence.borrow_mut() = Some(divergence);
+    }
+}
+
+impl RecordedRequest {
+    fn new(variant: &ValidVariant, result: &Result<(), WlConfigurationError>) -> Self {
+        Self {
+            variant: variant.idx_str(),
+            profile: variant.profile.name.clone(),
+            error: result.as_ref().err().map(|err| err.to_string()),
+        }
+    }
+}
+
+impl RecordEntry {
+    /// Returns true for inputs of the daemon, the other entries are requests made by it.
+    pub fn is_input(&self) -> bool {
+        matches!(
+            self,
+            RecordEntry::Events(_)
+                | RecordEntry::Matched
+                | RecordEntry::Request(_)
+                | RecordEntry::Reload(_)
+                | RecordEntry::ConfirmTimeout(_)
+        )
+    }
+}
+
+impl Display for RecordEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordEntry::Settings(..) => write!(f, "settings"),
+            RecordEntry::Events(events) => {
+                let events: Vec<_> = events.iter().map(|e| e.to_string()).collect();
+                write!(f, "events {}", events.join(", "))
+            }
+            RecordEntry::Heads(_) => write!(f, "export of heads"),
+            RecordEntry::Test(r) => write!(f, "test of variant {}:{:?}", r.variant, r.profile),
+            RecordEntry::Apply(r) => write!(f, "apply of variant {}:{:?}", r.variant, r.profile),
+            RecordEntry::Matched => write!(f, "matched profiles"),
+            RecordEntry::Request(request) => write!(f, "request {request:?}"),
+            RecordEntry::Reload(_) => write!(f, "reloaded settings"),
+            RecordEntry::ConfirmTimeout(id) => write!(f, "timeout of confirmation {id}"),
+        }
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}", self.line)?;
+        if let Some(time) = self.time {
+            write!(f, " ({time}ms)")?;
+        }
+        write!(f, ": expected {}, got {}", self.expected, self.got)
+    }
+}
+
+#[derive(Debug, Snafu)]
+#[snafu(context(suffix(Ctx)))]
+#[snafu(visibility(pub(crate)))]
+pub enum RecordError {
+    #[snafu(display("[{location}] Cannot create recording {path:?}"))]
+    CreateRecording {
+        source: std::io::Error,
+        location: Location,
+        path: PathBuf,
+    },
+    #[snafu(display("[{location}] Cannot write record"))]
+    WriteRecord {
+        source: std::io::Error,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot serialize record"))]
+    SerializeRecord {
+        source: ron::Error,
+        location: Location,
+    },
+    #[snafu(display("[{location}] Cannot read recording {path:?}"))]
+    ReadRecording {
+        source: std::io::Error,
+        location: Location,
+        path: PathBuf,
+    },
+    #[snafu(display("[{location}] Cannot parse line {line} of the recording"))]
+    ParseRecord {
+        source: ron::error::SpannedError,
+        location: Location,
+        line: usize,
+    },
+    #[snafu(display("[{location}] The recording does not start with the settings"))]
+    MissingSettings { location: Location },
+}
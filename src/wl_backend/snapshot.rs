@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+
+#[allow(unused_imports)]
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::error;
+use crate::matching::{ProfileMatcher, RankedVariants};
+use crate::profile::ConverterSettings;
+use crate::search::SearchField;
+use crate::variant::ValidVariant;
+
+use super::{head_set_key, WlHead};
+
+/// The state of the heads at some point, e.g. before a variant was applied.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HeadSnapshot {
+    heads: VecDeque<WlHead>,
+}
+
+impl HeadSnapshot {
+    pub fn new(heads: VecDeque<WlHead>) -> Self {
+        Self { heads }
+    }
+
+    pub fn heads(&self) -> &VecDeque<WlHead> {
+        &self.heads
+    }
+
+    /// Returns true if the snapshot has been taken of the same set of heads.
+    pub fn fits(&self, current_heads: &VecDeque<WlHead>) -> bool {
+        head_set_key(&self.heads) == head_set_key(current_heads)
+    }
+
+    /// Names of the heads in the snapshot that are not among the current heads.
+    pub fn missing_heads(&self, current_heads: &VecDeque<WlHead>) -> Vec<String> {
+        self.heads
+            .iter()
+            .filter(|head| !current_heads.iter().any(|h| h.name() == head.name()))
+            .map(|head| head.name().to_string())
+            .collect()
+    }
+
+    /// Builds a variant restoring the snapshot on the currently connected heads.
+    ///
+    /// The heads are found again by their names. Returns `None` if the snapshot does not fit the
+    /// current heads anymore, e.g. because one of them has been disconnected.
+    pub fn variant(&self, current_heads: VecDeque<WlHead>) -> Option<ValidVariant> {
+        let converter = ConverterSettings::default()
+            .profile_name("snapshot".to_string())
+            .include_search_fields(vec![SearchField::Name])
+            .converter();
+        let profile = match converter.profile(self.heads.clone()) {
+            Ok(profile) => profile,
+            Err(err) => {
+                warn!("{}", error::report(&err));
+                return None;
+            }
+        };
+        let report = ProfileMatcher::create_report(profile, current_heads)?;
+        // the modes of the snapshot fit exactly and are ranked first
+        RankedVariants::new(&[report].into()).next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::profile::Position;
+    use crate::wl_backend::test_heads::{head_with_base, set_current_mode};
+    use crate::wl_backend::{WlBaseHead, WlHead};
+
+    use super::HeadSnapshot;
+
+    fn heads(positions: [Option<(i32, i32)>; 2]) -> VecDeque<WlHead> {
+        positions
+            .into_iter()
+            .enumerate()
+            .map(|(id, position)| {
+                let (x, y) = position.unwrap_or_default();
+                let base = WlBaseHead {
+                    name: format!("DP-{id}"),
+                    enabled: position.is_some(),
+                    position: Position { x, y },
+                    scale: 1.0,
+                    ..Default::default()
+                };
+                let mut head = head_with_base(id, base, &[60000, 30000]);
+                if position.is_some() {
+                    set_current_mode(&mut head, 1);
+                }
+                head
+            })
+            .collect()
+    }
+
+    #[test]
+    fn restore_snapshot() {
+        let snapshot = HeadSnapshot::new(heads([Some((1920, 0)), None]));
+        // the state of the heads changed after the snapshot
+        let variant = snapshot.variant(heads([None, Some((0, 0))])).unwrap();
+        let pairing = |name: &str| {
+            let pairing = variant.pairings.iter().find(|p| p.wl_head().name() == name);
+            pairing.unwrap().clone()
+        };
+        let dp0 = pairing("DP-0");
+        assert!(dp0.output().enable);
+        assert_eq!(dp0.output().position, Some(Position { x: 1920, y: 0 }));
+        assert_eq!(dp0.wl_mode().map(|m| m.refresh()), Some(30000));
+        assert!(!pairing("DP-1").output().enable);
+
+        // a head of the snapshot is missing
+        let mut current = heads([None, None]);
+        current.pop_back();
+        assert_eq!(snapshot.missing_heads(&current), vec!["DP-1".to_string()]);
+        assert!(snapshot.variant(current).is_none());
+    }
+}
@@ -26,8 +26,8 @@ use crate::profile::{AdaptiveSyncState, Transform};
 use crate::profile::{Mode, Output};
 use crate::variant::ValidVariant;
 use crate::wl_backend::{
-    DeadHeadCtx, DeadModeCtx, ForeignId, HeadCountMismatchCtx, UnknownHeadCtx, UnknownModeCtx,
-    WlBackend, WlBackendEvent, WlConfigurationError, WlHead, WlStore,
+    DeadHeadCtx, DeadModeCtx, EdidReader, ForeignId, HeadCountMismatchCtx, UnknownHeadCtx,
+    UnknownModeCtx, WlBackend, WlBackendEvent, WlConfigurationError, WlHead, WlStore,
 };
 
 type WlrootsStore = WlStore<ZwlrOutputHeadV1, ZwlrOutputModeV1, ObjectId>;
@@ -40,6 +40,7 @@ pub struct WlrootsBackend {
     connection: Connection,
     queue_handle: QueueHandle<Self>,
     event_queue: VecDeque<WlBackendEvent>,
+    edid_reader: EdidReader,
 }
 
 impl WlBackend for WlrootsBackend {
@@ -86,6 +87,7 @@ impl WlrootsBackend {
             connection: connection.clone(),
             queue_handle,
             event_queue: Default::default(),
+            edid_reader: Default::default(),
         };
 
         Ok((backend, WaylandSource::new(connection, event_queue)))
@@ -234,6 +236,12 @@ fn configure_head_with_pairing(
     Ok(())
 }
 
+impl AsMut<WlrootsBackend> for WlrootsBackend {
+    fn as_mut(&mut self) -> &mut WlrootsBackend {
+        self
+    }
+}
+
 impl ForeignId for ZwlrOutputHeadV1 {
     type Id = ObjectId;
     fn foreign_id(&self) -> Self::Id {
@@ -36,6 +36,13 @@ impl Dispatch<ZwlrOutputHeadV1, ()> for WlrootsBackend {
         match event {
             ZwlrOutputHeadEvent::Name { name } => {
                 trace!("[Event::Name] {:?}", name);
+                head.base.edid = match backend.edid_reader.read(&name) {
+                    Ok(edid) => Some(edid),
+                    Err(err) => {
+                        debug!("No EDID for {:?}: {}", name, error::report(&err));
+                        None
+                    }
+                };
                 head.base.name = name
             }
             ZwlrOutputHeadEvent::Description { description } => {